@@ -0,0 +1,153 @@
+use crate::{Column, Row};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// How the values of a column are combined over the files of a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rule {
+    /// First non-empty value
+    First,
+    /// Smallest value, compared as numbers if all values are numeric
+    Min,
+    /// Largest value, compared as numbers if all values are numeric
+    Max,
+    /// Number of non-empty values
+    Count,
+    /// Distinct values in the order found
+    List,
+}
+
+impl Rule {
+    const ALL: &[Rule] = &[Rule::First, Rule::Min, Rule::Max, Rule::Count, Rule::List];
+
+    fn name(self) -> &'static str {
+        match self {
+            Rule::First => "first",
+            Rule::Min => "min",
+            Rule::Max => "max",
+            Rule::Count => "count",
+            Rule::List => "list",
+        }
+    }
+
+    fn apply(self, values: &[&str], separator: &str) -> String {
+        match self {
+            Rule::First => values.first().copied().unwrap_or_default().to_string(),
+            Rule::Min | Rule::Max => {
+                let numbers: Option<Vec<f64>> =
+                    values.iter().map(|v| v.trim().parse().ok()).collect();
+                let less = |a: usize, b: usize| match &numbers {
+                    Some(numbers) => numbers[a] < numbers[b],
+                    None => values[a] < values[b],
+                };
+                (0..values.len())
+                    .reduce(|a, b| {
+                        let better = if self == Rule::Min {
+                            less(b, a)
+                        } else {
+                            less(a, b)
+                        };
+                        if better { b } else { a }
+                    })
+                    .map(|i| values[i].to_string())
+                    .unwrap_or_default()
+            }
+            Rule::Count => values.len().to_string(),
+            Rule::List => {
+                let mut distinct: Vec<&str> = Vec::new();
+                for value in values {
+                    if !distinct.contains(value) {
+                        distinct.push(value);
+                    }
+                }
+                distinct.join(separator)
+            }
+        }
+    }
+}
+
+/// Parse `TAG=RULE` (e.g. `SliceLocation=min`)
+pub(crate) fn parse_rule_arg(s: &str) -> Result<(Column, Rule)> {
+    let (tag, rule) = s
+        .split_once('=')
+        .with_context(|| format!("Invalid aggregation (expected TAG=RULE): {s}"))?;
+    let rule = Rule::ALL
+        .iter()
+        .copied()
+        .find(|r| r.name() == rule)
+        .with_context(|| format!("Unknown aggregation rule: {rule}"))?;
+    Ok((tag.parse()?, rule))
+}
+
+/// Header and rows of the table with a row per distinct value of `key`, in the order found.
+///
+/// `names` gives the column names; columns aggregated by a rule other than `first`
+/// are named with the rule appended (e.g. `SliceLocation (min)`).
+pub(crate) fn aggregate(
+    rows: &[(PathBuf, Row)],
+    key: &Column,
+    columns: &[(Column, Rule)],
+    names: impl Fn(&Column) -> String,
+    defaults: &HashMap<Column, String>,
+    separator: &str,
+) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut groups: Vec<(&str, Vec<&Row>)> = Vec::new();
+    let mut indices: HashMap<&str, usize> = HashMap::new();
+    for (_, row) in rows {
+        let value = crate::cell(row, key, defaults);
+        let index = *indices.entry(value).or_insert_with(|| {
+            groups.push((value, Vec::new()));
+            groups.len() - 1
+        });
+        groups[index].1.push(row);
+    }
+
+    let mut header = vec![names(key), "NumFiles".to_string()];
+    header.extend(columns.iter().map(|(column, rule)| match rule {
+        Rule::First => names(column),
+        rule => format!("{} ({})", names(column), rule.name()),
+    }));
+    let table = groups
+        .iter()
+        .map(|(value, group)| {
+            let mut record = vec![value.to_string(), group.len().to_string()];
+            for (column, rule) in columns {
+                let values: Vec<_> = group
+                    .iter()
+                    .map(|row| crate::cell(row, column, defaults))
+                    .filter(|v| !v.is_empty())
+                    .collect();
+                record.push(rule.apply(&values, separator));
+            }
+            record
+        })
+        .collect();
+    (header, table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let values = ["2.5", "-10", "10"];
+        assert_eq!(Rule::First.apply(&values, "\\"), "2.5");
+        assert_eq!(Rule::Min.apply(&values, "\\"), "-10");
+        assert_eq!(Rule::Max.apply(&values, "\\"), "10");
+        assert_eq!(Rule::Count.apply(&values, "\\"), "3");
+        assert_eq!(Rule::Max.apply(&["AXIAL", "LOCALIZER"], "\\"), "LOCALIZER");
+        assert_eq!(Rule::List.apply(&["CT", "PT", "CT"], "|"), "CT|PT");
+        assert_eq!(Rule::Min.apply(&[], "\\"), "");
+    }
+
+    #[test]
+    fn test_parse_rule_arg() {
+        let (column, rule) = parse_rule_arg("SliceLocation=max").unwrap();
+        assert_eq!(column.name(), "SliceLocation");
+        assert_eq!(rule, Rule::Max);
+        assert!(parse_rule_arg("SliceLocation").is_err());
+        assert!(parse_rule_arg("SliceLocation=median").is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// An entry of the central directory of a zip archive
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    /// Offset of the local file header
+    offset: u32,
+}
+
+type Index = Arc<HashMap<String, Entry>>;
+
+/// Central directories of the archives read so far, so that each is parsed only once
+static INDICES: LazyLock<Mutex<HashMap<PathBuf, Index>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Whether `path` is a zip archive given as an input
+pub(crate) fn is_archive(path: &Path) -> bool {
+    is_zip(path) && path.is_file()
+}
+
+/// Split a path to an entry of a zip archive (`archive.zip/dir/file.dcm`)
+/// into the archive and the entry name
+pub(crate) fn split_entry_path(path: &Path) -> Option<(&Path, String)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_zip(ancestor) && ancestor.is_file())?;
+    let name = path
+        .strip_prefix(archive)
+        .ok()?
+        .iter()
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((archive, name))
+}
+
+/// Paths of the entries of the archive which look like DICOM files
+/// (`.dcm` or no extension), in the order stored in the archive
+pub(crate) fn list_entries(archive: &Path) -> Result<Vec<PathBuf>> {
+    let mut file = File::open(archive).with_context(|| format!("Failed to open {archive:?}"))?;
+    let entries = read_central_directory(&mut file)
+        .with_context(|| format!("Invalid zip archive {archive:?}"))?;
+    let paths = entries
+        .iter()
+        .map(|(name, _)| name)
+        .filter(|name| looks_like_dicom(name))
+        .map(|name| archive.join(name))
+        .collect();
+    INDICES.lock().unwrap().insert(
+        archive.to_path_buf(),
+        Arc::new(entries.into_iter().collect()),
+    );
+    Ok(paths)
+}
+
+fn looks_like_dicom(name: &str) -> bool {
+    if name.ends_with('/') || name.starts_with("__MACOSX/") {
+        return false;
+    }
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    match file_name.rsplit_once('.') {
+        Some((_, ext)) => ext.eq_ignore_ascii_case("dcm"),
+        None => true,
+    }
+}
+
+/// Read and decompress an entry of the archive into memory
+pub(crate) fn read_entry(archive: &Path, name: &str) -> Result<Vec<u8>> {
+    let mut file = File::open(archive).with_context(|| format!("Failed to open {archive:?}"))?;
+    let cached = INDICES.lock().unwrap().get(archive).cloned();
+    let index = match cached {
+        Some(index) => index,
+        None => {
+            let index: Index = Arc::new(read_central_directory(&mut file)?.into_iter().collect());
+            INDICES
+                .lock()
+                .unwrap()
+                .insert(archive.to_path_buf(), index.clone());
+            index
+        }
+    };
+    let entry = *index
+        .get(name)
+        .with_context(|| format!("No entry {name:?} in {archive:?}"))?;
+    if entry.flags & 1 != 0 {
+        bail!("Encrypted entries are not supported");
+    }
+
+    file.seek(SeekFrom::Start(entry.offset.into()))?;
+    let mut header = [0; 30];
+    file.read_exact(&mut header)?;
+    if u32_at(&header, 0) != 0x04034b50 {
+        bail!("Invalid local file header of {name:?}");
+    }
+    let skip = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+    file.seek(SeekFrom::Current(skip))?;
+    let compressed = file.take(entry.compressed_size.into());
+    let mut data = Vec::with_capacity(entry.size as usize);
+    match entry.method {
+        0 => compressed.take(entry.size.into()).read_to_end(&mut data)?,
+        8 => flate2::read::DeflateDecoder::new(compressed).read_to_end(&mut data)?,
+        method => bail!("Unsupported compression method {method} of {name:?}"),
+    };
+    if data.len() != entry.size as usize || crc32fast::hash(&data) != entry.crc {
+        bail!("Corrupted entry {name:?}");
+    }
+    Ok(data)
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Read the names and locations of the entries from the central directory
+fn read_central_directory(file: &mut File) -> Result<Vec<(String, Entry)>> {
+    // the end of central directory record is followed by a comment of at most 64 KiB
+    let len = file.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + 0xffff);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail, i) == 0x06054b50)
+        .context("Missing end of central directory record")?;
+    let n_entries = u16_at(&tail, end + 10);
+    let size = u32_at(&tail, end + 12);
+    let offset = u32_at(&tail, end + 16);
+    if n_entries == 0xffff || size == u32::MAX || offset == u32::MAX {
+        bail!("ZIP64 archives are not supported");
+    }
+
+    file.seek(SeekFrom::Start(offset.into()))?;
+    let mut directory = vec![0; size as usize];
+    file.read_exact(&mut directory)?;
+    let mut entries = Vec::with_capacity(n_entries.into());
+    let mut pos = 0;
+    for _ in 0..n_entries {
+        let header = directory
+            .get(pos..pos + 46)
+            .context("Truncated central directory")?;
+        if u32_at(header, 0) != 0x02014b50 {
+            bail!("Invalid central directory");
+        }
+        let name_len = u16_at(header, 28) as usize;
+        let extra_len = u16_at(header, 30) as usize;
+        let comment_len = u16_at(header, 32) as usize;
+        let entry = Entry {
+            flags: u16_at(header, 8),
+            method: u16_at(header, 10),
+            crc: u32_at(header, 16),
+            compressed_size: u32_at(header, 20),
+            size: u32_at(header, 24),
+            offset: u32_at(header, 42),
+        };
+        let name = directory
+            .get(pos + 46..pos + 46 + name_len)
+            .context("Truncated central directory")?;
+        entries.push((String::from_utf8_lossy(name).into_owned(), entry));
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// Writer of zip archives with stored (uncompressed) entries
+pub(crate) struct ZipWriter<W> {
+    output: W,
+    offset: u32,
+    central_directory: Vec<u8>,
+    n_entries: u16,
+}
+
+/// MS-DOS date of 1980-01-01
+const DOS_DATE: u16 = 0x21;
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(output: W) -> Self {
+        ZipWriter {
+            output,
+            offset: 0,
+            central_directory: Vec::new(),
+            n_entries: 0,
+        }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let crc = crc32fast::hash(data);
+        let size = u32::try_from(data.len()).map_err(|_| io::Error::other("Entry too large"))?;
+        let name_len = name.len() as u16;
+
+        let mut local = Vec::with_capacity(30 + name.len());
+        local.extend(0x04034b50u32.to_le_bytes());
+        local.extend(20u16.to_le_bytes()); // version needed to extract
+        local.extend(0u16.to_le_bytes()); // flags
+        local.extend(0u16.to_le_bytes()); // stored
+        local.extend(0u16.to_le_bytes()); // time
+        local.extend(DOS_DATE.to_le_bytes());
+        local.extend(crc.to_le_bytes());
+        local.extend(size.to_le_bytes());
+        local.extend(size.to_le_bytes());
+        local.extend(name_len.to_le_bytes());
+        local.extend(0u16.to_le_bytes()); // extra field length
+        local.extend(name.as_bytes());
+        self.output.write_all(&local)?;
+        self.output.write_all(data)?;
+
+        let central = &mut self.central_directory;
+        central.extend(0x02014b50u32.to_le_bytes());
+        central.extend(20u16.to_le_bytes()); // version made by
+        central.extend(20u16.to_le_bytes()); // version needed to extract
+        central.extend(0u16.to_le_bytes()); // flags
+        central.extend(0u16.to_le_bytes()); // stored
+        central.extend(0u16.to_le_bytes()); // time
+        central.extend(DOS_DATE.to_le_bytes());
+        central.extend(crc.to_le_bytes());
+        central.extend(size.to_le_bytes());
+        central.extend(size.to_le_bytes());
+        central.extend(name_len.to_le_bytes());
+        central.extend([0; 8]); // extra field, comment, disk number, internal attributes
+        central.extend(0u32.to_le_bytes()); // external attributes
+        central.extend(self.offset.to_le_bytes());
+        central.extend(name.as_bytes());
+
+        self.offset += local.len() as u32 + size;
+        self.n_entries += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.output.write_all(&self.central_directory)?;
+        let mut end = Vec::with_capacity(22);
+        end.extend(0x06054b50u32.to_le_bytes());
+        end.extend([0; 4]); // disk numbers
+        end.extend(self.n_entries.to_le_bytes());
+        end.extend(self.n_entries.to_le_bytes());
+        end.extend((self.central_directory.len() as u32).to_le_bytes());
+        end.extend(self.offset.to_le_bytes());
+        end.extend(0u16.to_le_bytes()); // comment length
+        self.output.write_all(&end)?;
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_archive() {
+        let dir = crate::test_dir("archive");
+        let archive = dir.join("test.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.add("series/", b"").unwrap();
+        zip.add("series/IM0001", b"first").unwrap();
+        zip.add("series/IM0002.dcm", b"second").unwrap();
+        zip.add("README.txt", b"readme").unwrap();
+        zip.finish().unwrap();
+
+        let entries = list_entries(&archive).unwrap();
+        assert_eq!(
+            entries,
+            [
+                archive.join("series/IM0001"),
+                archive.join("series/IM0002.dcm")
+            ]
+        );
+        let (path, name) = split_entry_path(&entries[1]).unwrap();
+        assert_eq!(path, archive);
+        assert_eq!(name, "series/IM0002.dcm");
+        assert_eq!(read_entry(path, &name).unwrap(), b"second");
+        assert!(read_entry(path, "missing.dcm").is_err());
+        assert_eq!(split_entry_path(&dir.join("series/IM0001")), None);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{archive, dicomdir};
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Labels of the inputs, written in the Cohort column of the files found in them
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Cohorts {
+    /// Canonicalized input paths and their labels
+    labels: Vec<(PathBuf, String)>,
+}
+
+impl Cohorts {
+    /// Parse `INPUT=LABEL` arguments, and label the other directories in `inferred`
+    /// with their names
+    pub fn new(args: &[String], inferred: &[PathBuf]) -> Result<Self> {
+        let mut labels = Vec::new();
+        for arg in args {
+            let (input, label) = arg
+                .rsplit_once('=')
+                .with_context(|| format!("Invalid label (expected INPUT=LABEL): {arg}"))?;
+            labels.push((normalize(Path::new(input)), label.to_string()));
+        }
+        for input in inferred.iter().filter(|input| input.is_dir()) {
+            let input = normalize(input);
+            if labels.iter().any(|(labeled, _)| *labeled == input) {
+                continue;
+            }
+            let Some(name) = input.file_name() else {
+                continue;
+            };
+            let label = name.to_string_lossy().into_owned();
+            labels.push((input, label));
+        }
+        Ok(Cohorts { labels })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Label of the innermost labeled input containing `path` (empty if none)
+    pub fn label(&self, path: &Path) -> &str {
+        let path = archive::split_entry_path(path).map_or(path, |(archive, _)| archive);
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.labels
+            .iter()
+            .filter(|(input, _)| path.starts_with(input))
+            .max_by_key(|(input, _)| input.components().count())
+            .map_or("", |(_, label)| label)
+    }
+}
+
+/// Canonicalize an input path, taking the directory of a DICOMDIR
+/// since the files it references are found next to it
+fn normalize(input: &Path) -> PathBuf {
+    let input = if dicomdir::is_dicomdir(input) {
+        input.parent().unwrap_or(input)
+    } else {
+        input
+    };
+    std::fs::canonicalize(input).unwrap_or_else(|_| input.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cohort_label() {
+        let cohorts = Cohorts::new(
+            &[
+                "/data/a=control".to_string(),
+                "/data/a/treated=treatment".to_string(),
+                "/data/b/DICOMDIR=b".to_string(),
+            ],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(cohorts.label(Path::new("/data/a/1.dcm")), "control");
+        assert_eq!(
+            cohorts.label(Path::new("/data/a/treated/1.dcm")),
+            "treatment"
+        );
+        assert_eq!(cohorts.label(Path::new("/data/b/IMAGES/1")), "b");
+        assert_eq!(cohorts.label(Path::new("/data/ab/1.dcm")), "");
+        assert!(Cohorts::new(&["/data/a".to_string()], &[]).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{Column, Row};
+use anyhow::{Context, Result, bail};
+use dicom_dictionary_std::tags;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Tags required to have a value, per SOP class UID
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConformanceProfile {
+    required: HashMap<String, Vec<Column>>,
+}
+
+impl ConformanceProfile {
+    /// Read a profile from a JSON object mapping SOP class UIDs to arrays of tags
+    pub fn read(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+        Self::parse(&content).with_context(|| format!("Invalid conformance profile {path:?}"))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let Value::Object(classes) = serde_json::from_str(content)? else {
+            bail!("Expected an object of SOP class UIDs");
+        };
+        let mut required = HashMap::new();
+        for (uid, tags) in classes {
+            let Value::Array(tags) = tags else {
+                bail!("Expected an array of tags for {uid}");
+            };
+            let columns = tags
+                .iter()
+                .map(|tag| match tag {
+                    Value::String(tag) => Ok(tag.parse()?),
+                    tag => bail!("Expected a tag: {tag}"),
+                })
+                .collect::<Result<_>>()?;
+            required.insert(uid, columns);
+        }
+        Ok(ConformanceProfile { required })
+    }
+
+    /// Columns to extract for checking the files
+    pub fn columns(&self) -> Vec<Column> {
+        let mut columns = vec![Column::Tag(tags::SOP_CLASS_UID)];
+        for column in self.required.values().flatten() {
+            if !columns.contains(column) {
+                columns.push(*column);
+            }
+        }
+        columns
+    }
+
+    /// SOP class UID of the row and the required columns without a value,
+    /// or `None` if the profile has no entry for the SOP class
+    pub fn violations<'a>(&self, row: &'a Row) -> Option<(&'a str, Vec<Column>)> {
+        let uid = row
+            .get(&Column::Tag(tags::SOP_CLASS_UID))
+            .map(|uid| uid.trim_end_matches(['\0', ' ']))
+            .unwrap_or_default();
+        let required = self.required.get(uid)?;
+        let missing = required
+            .iter()
+            .filter(|column| row.get(column).is_none_or(|value| value.trim().is_empty()))
+            .copied()
+            .collect();
+        Some((uid, missing))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_dictionary_std::uids;
+
+    #[test]
+    fn test_violations() {
+        let profile = ConformanceProfile::parse(&format!(
+            r#"{{"{}": ["PatientID", "Modality", "ReferencedImageSequence/0/ReferencedSOPInstanceUID"]}}"#,
+            uids::CT_IMAGE_STORAGE
+        ))
+        .unwrap();
+        assert_eq!(profile.columns().len(), 4);
+        let row = Row::from([
+            (
+                Column::Tag(tags::SOP_CLASS_UID),
+                uids::CT_IMAGE_STORAGE.to_string(),
+            ),
+            (Column::Tag(tags::PATIENT_ID), "ID".to_string()),
+            (Column::Tag(tags::MODALITY), " ".to_string()),
+        ]);
+        let (uid, missing) = profile.violations(&row).unwrap();
+        assert_eq!(uid, uids::CT_IMAGE_STORAGE);
+        assert_eq!(missing[0], Column::Tag(tags::MODALITY));
+        assert_eq!(missing.len(), 2);
+        let row = Row::from([(
+            Column::Tag(tags::SOP_CLASS_UID),
+            uids::MR_IMAGE_STORAGE.to_string(),
+        )]);
+        assert_eq!(profile.violations(&row), None);
+        assert!(ConformanceProfile::parse(r#"["PatientID"]"#).is_err());
+        assert!(ConformanceProfile::parse(r#"{"1.2": "PatientID"}"#).is_err());
+    }
+}
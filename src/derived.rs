@@ -0,0 +1,526 @@
+use crate::filter::parse_da;
+use crate::pixel::{PixelFrames, PixelStats, pixel_stats};
+use crate::{Column, Row, md5};
+use anyhow::{Context, Result, anyhow, bail};
+use dicom_dictionary_std::{tags, uids};
+use dicom_encoding::{Endianness, TransferSyntaxIndex};
+use dicom_object::{DefaultDicomObject, FileMetaTable};
+use dicom_parser::dataset::{DataSetReader, DataToken};
+use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Values computed from a file rather than read from a single element.
+///
+/// Derived columns are requested with an `@` prefixed name (e.g. `@num_fragments`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Derived {
+    /// Number of entries in the Basic Offset Table of encapsulated pixel data
+    NumFragments,
+    /// VR encoding and byte order of the transfer syntax (e.g. `ExplicitVRLittleEndian`)
+    Encoding,
+    /// Whether the transfer syntax is other than the uncompressed little/big endian syntaxes
+    Compressed,
+    /// PixelSpacing (or ImagerPixelSpacing if absent) as `row\col` in mm with fixed precision
+    PixelSpacingMm,
+    /// Number of top-level elements read (excluding the file meta group)
+    ElementCount,
+    /// Minimum stored pixel value
+    PixelMin,
+    /// Maximum stored pixel value
+    PixelMax,
+    /// Mean stored pixel value
+    PixelMean,
+    /// Age in whole years at StudyDate, from PatientBirthDate
+    AgeYears,
+    /// Whether the length of native pixel data matches the image attributes
+    PixelDataSizeOk,
+    /// Whether an ICC profile is embedded, at the top level or in an optical path
+    HasIccProfile,
+    /// WindowCenter and WindowWidth paired as `center/width` values
+    Window,
+    /// MD5 of the other extracted values, computed by `metadata_hash`
+    MetadataHash,
+    /// Position of the file in its series, assigned by `assign_series_indices`
+    SeriesIndex,
+}
+
+impl Derived {
+    const ALL: &[Derived] = &[
+        Derived::NumFragments,
+        Derived::Encoding,
+        Derived::Compressed,
+        Derived::PixelSpacingMm,
+        Derived::ElementCount,
+        Derived::PixelMin,
+        Derived::PixelMax,
+        Derived::PixelMean,
+        Derived::AgeYears,
+        Derived::PixelDataSizeOk,
+        Derived::HasIccProfile,
+        Derived::Window,
+        Derived::MetadataHash,
+        Derived::SeriesIndex,
+    ];
+
+    /// Column name of the derived value
+    pub fn name(self) -> &'static str {
+        match self {
+            Derived::NumFragments => "@num_fragments",
+            Derived::Encoding => "@encoding",
+            Derived::Compressed => "@compressed",
+            Derived::PixelSpacingMm => "@pixel_spacing_mm",
+            Derived::ElementCount => "@element_count",
+            Derived::PixelMin => "@pixel_min",
+            Derived::PixelMax => "@pixel_max",
+            Derived::PixelMean => "@pixel_mean",
+            Derived::AgeYears => "@age_years",
+            Derived::PixelDataSizeOk => "@pixeldata_size_ok",
+            Derived::HasIccProfile => "@has_icc_profile",
+            Derived::Window => "@window",
+            Derived::MetadataHash => "@metadata_hash",
+            Derived::SeriesIndex => "@series_index",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|d| d.name() == name)
+    }
+
+    /// Whether the pixel data has to be read to compute the value
+    pub(crate) fn needs_pixel_data(self) -> bool {
+        matches!(
+            self,
+            Derived::PixelMin | Derived::PixelMax | Derived::PixelMean
+        )
+    }
+
+    /// Compute the value for the file in `ctx`
+    pub(crate) fn compute(self, ctx: &DerivedContext) -> Result<String> {
+        let obj = ctx.obj;
+        match self {
+            Derived::NumFragments => Ok(read_offset_table_len(ctx.input)?
+                .map(|n| n.to_string())
+                .unwrap_or_default()),
+            Derived::Encoding => encoding(obj.meta().transfer_syntax()),
+            Derived::Compressed => Ok(is_compressed(obj.meta().transfer_syntax()).to_string()),
+            Derived::PixelSpacingMm => pixel_spacing_mm(obj, ctx.separator),
+            Derived::ElementCount => Ok(obj.iter().count().to_string()),
+            Derived::PixelMin => Ok(ctx
+                .pixel_stats()?
+                .map(|s| s.min.to_string())
+                .unwrap_or_default()),
+            Derived::PixelMax => Ok(ctx
+                .pixel_stats()?
+                .map(|s| s.max.to_string())
+                .unwrap_or_default()),
+            Derived::PixelMean => Ok(ctx
+                .pixel_stats()?
+                .map(|s| format!("{:.3}", s.mean))
+                .unwrap_or_default()),
+            Derived::AgeYears => {
+                let date = |tag| obj.get(tag).and_then(|elm| elm.to_str().ok());
+                Ok(date(tags::PATIENT_BIRTH_DATE)
+                    .zip(date(tags::STUDY_DATE))
+                    .and_then(|(birth, study)| age_years(&birth, &study))
+                    .map(|age| age.to_string())
+                    .unwrap_or_default())
+            }
+            Derived::PixelDataSizeOk => {
+                if is_compressed(obj.meta().transfer_syntax()) {
+                    return Ok(String::new());
+                }
+                let Some(expected) = expected_pixel_data_len(obj)? else {
+                    return Ok(String::new());
+                };
+                Ok(read_pixel_data_len(ctx.input)?
+                    .map(|len| pixel_data_size_ok(len, expected).to_string())
+                    .unwrap_or_default())
+            }
+            Derived::HasIccProfile => Ok(has_icc_profile(obj).to_string()),
+            Derived::Window => {
+                let values = |tag| -> Result<Vec<String>> {
+                    Ok(match obj.get(tag) {
+                        Some(elm) => elm
+                            .to_multi_str()?
+                            .iter()
+                            .map(|value| value.trim().to_string())
+                            .collect(),
+                        None => Vec::new(),
+                    })
+                };
+                Ok(pair_windows(
+                    &values(tags::WINDOW_CENTER)?,
+                    &values(tags::WINDOW_WIDTH)?,
+                    ctx.separator,
+                ))
+            }
+            // depend on the other columns of the row or on the other rows
+            Derived::MetadataHash | Derived::SeriesIndex => Ok(String::new()),
+        }
+    }
+}
+
+/// A file being processed, along with values shared by several derived columns
+pub(crate) struct DerivedContext<'a> {
+    pub input: &'a Path,
+    pub obj: &'a DefaultDicomObject,
+    pub pixel_frames: PixelFrames,
+    /// Separator of multiple values (`--value-separator`)
+    pub separator: &'a str,
+    pixel_stats: OnceCell<Result<Option<PixelStats>, String>>,
+}
+
+impl<'a> DerivedContext<'a> {
+    pub fn new(
+        input: &'a Path,
+        obj: &'a DefaultDicomObject,
+        pixel_frames: PixelFrames,
+        separator: &'a str,
+    ) -> Self {
+        DerivedContext {
+            input,
+            obj,
+            pixel_frames,
+            separator,
+            pixel_stats: OnceCell::new(),
+        }
+    }
+
+    /// Pixel statistics, computed on first use
+    fn pixel_stats(&self) -> Result<Option<PixelStats>> {
+        self.pixel_stats
+            .get_or_init(|| pixel_stats(self.obj, self.pixel_frames).map_err(|e| e.to_string()))
+            .clone()
+            .map_err(|e| anyhow!(e))
+    }
+}
+
+/// MD5 of the extracted values in the order of the column specs, so that files with
+/// the same metadata hash the same regardless of the byte layout.
+///
+/// Derived values and value lengths depend on the encoding and are left out,
+/// and empty values are treated as absent.
+pub(crate) fn metadata_hash(row: &Row) -> String {
+    let mut entries: Vec<_> = row
+        .iter()
+        .filter(|(column, value)| {
+            !value.is_empty()
+                && !matches!(
+                    column,
+                    Column::Derived(_) | Column::Length(_) | Column::Cohort | Column::ItemCount(_)
+                )
+        })
+        .map(|(column, value)| (column.spec(), value.as_str()))
+        .collect();
+    entries.sort();
+    let mut data = Vec::new();
+    for (spec, value) in entries {
+        // separated by the unit and record separators
+        data.extend_from_slice(spec.as_bytes());
+        data.push(0x1f);
+        data.extend_from_slice(value.as_bytes());
+        data.push(0x1e);
+    }
+    md5::hex_digest(&data)
+}
+
+/// Set `@series_index` of the rows to the 0-based position of the file in its series
+/// (SeriesInstanceUID) sorted by InstanceNumber.
+///
+/// Files without a valid InstanceNumber come last in the order of their paths,
+/// and files without a SeriesInstanceUID get no index.
+pub(crate) fn assign_series_indices(rows: &mut [(PathBuf, Row)]) {
+    let mut series: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, (_, row)) in rows.iter().enumerate() {
+        if let Some(uid) = row
+            .get(&Column::Tag(tags::SERIES_INSTANCE_UID))
+            .filter(|uid| !uid.is_empty())
+        {
+            series.entry(uid.clone()).or_default().push(i);
+        }
+    }
+    for indices in series.values_mut() {
+        indices.sort_by_cached_key(|&i| {
+            let (path, row) = &rows[i];
+            let number = row
+                .get(&Column::Tag(tags::INSTANCE_NUMBER))
+                .and_then(|value| value.trim().parse::<i64>().ok());
+            (number.is_none(), number, path.clone())
+        });
+        for (position, &i) in indices.iter().enumerate() {
+            rows[i]
+                .1
+                .insert(Column::Derived(Derived::SeriesIndex), position.to_string());
+        }
+    }
+}
+
+/// Describe the VR encoding and byte order implied by a transfer syntax
+fn encoding(ts_uid: &str) -> Result<String> {
+    let ts = TransferSyntaxRegistry
+        .get(ts_uid)
+        .with_context(|| format!("Unknown transfer syntax: {ts_uid}"))?;
+    // implicit VR is only defined for the default transfer syntax
+    let vr = if ts.uid() == uids::IMPLICIT_VR_LITTLE_ENDIAN {
+        "Implicit"
+    } else {
+        "Explicit"
+    };
+    let endianness = match ts.endianness() {
+        Endianness::Little => "Little",
+        Endianness::Big => "Big",
+    };
+    Ok(format!("{vr}VR{endianness}Endian"))
+}
+
+/// Normalized pixel spacing, preferring PixelSpacing over ImagerPixelSpacing
+fn pixel_spacing_mm(obj: &DefaultDicomObject, separator: &str) -> Result<String> {
+    let Some(elm) = [tags::PIXEL_SPACING, tags::IMAGER_PIXEL_SPACING]
+        .into_iter()
+        .find_map(|tag| obj.get(tag))
+    else {
+        return Ok(String::new());
+    };
+    match elm.to_multi_float64()?.as_slice() {
+        [row, col] => Ok(format_spacing(*row, *col, separator)),
+        values => bail!("Expected 2 pixel spacing values but found {}", values.len()),
+    }
+}
+
+/// Whether ICCProfile is present at the top level or, as in whole slide images,
+/// in an item of OpticalPathSequence
+fn has_icc_profile(obj: &DefaultDicomObject) -> bool {
+    obj.get(tags::ICC_PROFILE).is_some()
+        || obj
+            .get(tags::OPTICAL_PATH_SEQUENCE)
+            .and_then(|elm| elm.items())
+            .is_some_and(|items| {
+                items
+                    .iter()
+                    .any(|item| item.get(tags::ICC_PROFILE).is_some())
+            })
+}
+
+/// Whole years from `birth` to `study` (DA values), `None` if either is malformed
+/// or the study precedes the birth
+fn age_years(birth: &str, study: &str) -> Option<u32> {
+    let birth = parse_da(birth)?;
+    parse_da(study)?.years_since(birth)
+}
+
+/// Length in bytes of native pixel data implied by the image attributes,
+/// `None` if Rows, Columns or BitsAllocated is missing
+fn expected_pixel_data_len(obj: &DefaultDicomObject) -> Result<Option<u64>> {
+    let value = |tag| -> Result<Option<u64>> {
+        obj.get(tag)
+            .map(|elm| Ok(elm.to_str()?.trim().parse::<u64>()?))
+            .transpose()
+    };
+    let (Some(rows), Some(columns), Some(bits_allocated)) = (
+        value(tags::ROWS)?,
+        value(tags::COLUMNS)?,
+        value(tags::BITS_ALLOCATED)?,
+    ) else {
+        return Ok(None);
+    };
+    let samples = value(tags::SAMPLES_PER_PIXEL)?.unwrap_or(1);
+    let frames = value(tags::NUMBER_OF_FRAMES)?.unwrap_or(1);
+    // 1 bit pixels are packed, so the total is rounded up to whole bytes
+    Ok(Some(
+        (rows * columns * samples * frames * bits_allocated).div_ceil(8),
+    ))
+}
+
+/// Whether the declared pixel data length is the expected one,
+/// allowing for the padding of odd lengths
+fn pixel_data_size_ok(len: u64, expected: u64) -> bool {
+    len == expected || (expected % 2 == 1 && len == expected + 1)
+}
+
+/// Pair the i-th center with the i-th width as `center/width`, joined with `separator`.
+///
+/// The shorter of the two lists is padded with empty values.
+fn pair_windows(centers: &[String], widths: &[String], separator: &str) -> String {
+    (0..centers.len().max(widths.len()))
+        .map(|i| {
+            let value = |values: &[String]| values.get(i).cloned().unwrap_or_default();
+            format!("{}/{}", value(centers), value(widths))
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn format_spacing(row: f64, col: f64, separator: &str) -> String {
+    format!("{row:.4}{separator}{col:.4}")
+}
+
+/// Whether the transfer syntax is not one of the uncompressed little/big endian syntaxes
+fn is_compressed(ts_uid: &str) -> bool {
+    // UIDs in the meta group may be padded with a trailing null
+    let ts_uid = ts_uid.trim_end_matches('\0');
+    ![
+        uids::IMPLICIT_VR_LITTLE_ENDIAN,
+        uids::EXPLICIT_VR_LITTLE_ENDIAN,
+        "1.2.840.10008.1.2.2",
+    ]
+    .contains(&ts_uid)
+}
+
+/// Count the entries of the Basic Offset Table of encapsulated pixel data.
+///
+/// Elements preceding the pixel data and the offset table itself are read,
+/// but the fragments are not.
+/// Returns `None` if the file has native pixel data or no pixel data at all.
+fn read_offset_table_len(path: &Path) -> Result<Option<usize>> {
+    // pixel sequences may also appear in nested data sets (e.g. icon images),
+    // so only the one at the root level is considered
+    let mut depth = 0;
+    let mut in_pixel_data = false;
+    for token in data_set_tokens(path)? {
+        match token? {
+            DataToken::SequenceStart { .. } => depth += 1,
+            DataToken::PixelSequenceStart if depth == 0 => in_pixel_data = true,
+            DataToken::PixelSequenceStart => depth += 1,
+            DataToken::SequenceEnd => depth -= 1,
+            DataToken::OffsetTable(table) if in_pixel_data => return Ok(Some(table.len())),
+            DataToken::ElementHeader(header) if depth == 0 && header.tag == tags::PIXEL_DATA => {
+                return Ok(None);
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Read the declared length of the root level pixel data without reading its value.
+///
+/// Returns `None` if the file has no pixel data or it has an undefined length.
+fn read_pixel_data_len(path: &Path) -> Result<Option<u64>> {
+    let mut depth = 0;
+    for token in data_set_tokens(path)? {
+        match token? {
+            DataToken::SequenceStart { .. } | DataToken::PixelSequenceStart => depth += 1,
+            DataToken::SequenceEnd => depth -= 1,
+            DataToken::ElementHeader(header) if depth == 0 && header.tag == tags::PIXEL_DATA => {
+                return Ok(header.len.get().map(u64::from));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Tokens of the data set of the file at `path`, read lazily after the file meta group.
+///
+/// As with `--no-preamble`, the file may start with the `DICM` magic code or
+/// directly with the file meta group.
+fn data_set_tokens(path: &Path) -> Result<impl Iterator<Item = Result<DataToken>>> {
+    let mut file = BufReader::new(crate::open_input(path)?);
+    let buf = file.fill_buf()?;
+    if buf.len() >= 132 && &buf[128..132] == b"DICM" {
+        file.consume(128);
+    }
+    let magic: &[u8] = if file.fill_buf()?.starts_with(b"DICM") {
+        b""
+    } else {
+        b"DICM"
+    };
+    let mut file = std::io::Read::chain(magic, file);
+    let meta = FileMetaTable::from_reader(&mut file)?;
+    let ts = TransferSyntaxRegistry
+        .get(meta.transfer_syntax())
+        .with_context(|| format!("Unknown transfer syntax: {}", meta.transfer_syntax()))?;
+    Ok(DataSetReader::new_with_ts(file, ts)?.map(|token| Ok(token?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(
+            encoding(uids::IMPLICIT_VR_LITTLE_ENDIAN).unwrap(),
+            "ImplicitVRLittleEndian"
+        );
+        assert_eq!(
+            encoding("1.2.840.10008.1.2.2").unwrap(),
+            "ExplicitVRBigEndian"
+        );
+        assert_eq!(
+            encoding(uids::JPEG_BASELINE8_BIT).unwrap(),
+            "ExplicitVRLittleEndian"
+        );
+        assert!(encoding("1.2.3.4").is_err());
+    }
+
+    #[test]
+    fn test_age_years() {
+        assert_eq!(age_years("19800615", "20240614"), Some(43));
+        assert_eq!(age_years("19800615", "20240615"), Some(44));
+        assert_eq!(age_years("20000229", "20010228"), Some(0));
+        assert_eq!(age_years("20000229", "20010301"), Some(1));
+        assert_eq!(age_years("20240101", "20231231"), None);
+        assert_eq!(age_years("", "20240101"), None);
+        assert_eq!(age_years("1980-06-15", "20240101"), None);
+    }
+
+    #[test]
+    fn test_pixel_data_size_ok() {
+        assert!(pixel_data_size_ok(512 * 512 * 2, 512 * 512 * 2));
+        assert!(!pixel_data_size_ok(512 * 512, 512 * 512 * 2));
+        assert!(pixel_data_size_ok(10, 9));
+        assert!(!pixel_data_size_ok(11, 10));
+    }
+
+    #[test]
+    fn test_metadata_hash() {
+        let row = Row::from([
+            (Column::Tag(tags::PATIENT_ID), "ID".to_string()),
+            (Column::Tag(tags::MODALITY), "CT".to_string()),
+        ]);
+        let mut other = row.clone();
+        other.insert(Column::Tag(tags::STUDY_ID), String::new());
+        other.insert(Column::Derived(Derived::Encoding), "x".to_string());
+        assert_eq!(metadata_hash(&row), metadata_hash(&other));
+        other.insert(Column::Tag(tags::MODALITY), "MR".to_string());
+        assert_ne!(metadata_hash(&row), metadata_hash(&other));
+    }
+
+    #[test]
+    fn test_pair_windows() {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            pair_windows(&strings(&["40", "300"]), &strings(&["400", "1500"]), "\\"),
+            "40/400\\300/1500"
+        );
+        assert_eq!(
+            pair_windows(&strings(&["40", "300"]), &strings(&["400"]), "\\"),
+            "40/400\\300/"
+        );
+        assert_eq!(
+            pair_windows(&strings(&["40"]), &strings(&["400", "1500"]), "|"),
+            "40/400|/1500"
+        );
+        assert_eq!(pair_windows(&[], &strings(&["400"]), "\\"), "/400");
+        assert_eq!(pair_windows(&[], &[], "\\"), "");
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        assert_eq!(format_spacing(0.5, 0.488281, "\\"), "0.5000\\0.4883");
+        assert_eq!(format_spacing(0.5, 0.488281, "|"), "0.5000|0.4883");
+    }
+
+    #[test]
+    fn test_is_compressed() {
+        assert!(!is_compressed(uids::IMPLICIT_VR_LITTLE_ENDIAN));
+        assert!(!is_compressed("1.2.840.10008.1.2.1\0"));
+        assert!(!is_compressed("1.2.840.10008.1.2.2"));
+        assert!(is_compressed(uids::JPEG_BASELINE8_BIT));
+        assert!(is_compressed(uids::DEFLATED_EXPLICIT_VR_LITTLE_ENDIAN));
+    }
+}
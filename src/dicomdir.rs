@@ -0,0 +1,60 @@
+use anyhow::{Context, Result};
+use dicom_dictionary_std::tags;
+use dicom_object::OpenFileOptions;
+use std::path::{Path, PathBuf};
+
+/// Whether the input is a DICOMDIR (by its file name)
+pub(crate) fn is_dicomdir(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.eq_ignore_ascii_case("DICOMDIR"))
+}
+
+/// Paths of the files referenced by the directory records of a DICOMDIR
+pub(crate) fn referenced_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let obj = OpenFileOptions::new()
+        .open_file(path)
+        .with_context(|| format!("Failed to read DICOMDIR {path:?}"))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let Some(records) = obj
+        .get(tags::DIRECTORY_RECORD_SEQUENCE)
+        .and_then(|elm| elm.items())
+    else {
+        return Ok(Vec::new());
+    };
+    let mut files = Vec::new();
+    for record in records {
+        // only the records of images, reports, etc. reference a file
+        let Some(file_id) = record.get(tags::REFERENCED_FILE_ID) else {
+            continue;
+        };
+        let components = file_id.to_multi_str()?;
+        let components: Vec<_> = components.iter().map(|c| c.trim()).collect();
+        files.push(resolve(base, &components));
+    }
+    Ok(files)
+}
+
+/// Join the components of a Referenced File ID to `base`, matching the names
+/// case-insensitively if they do not exist as written
+fn resolve(base: &Path, components: &[&str]) -> PathBuf {
+    let mut path = base.to_path_buf();
+    for component in components {
+        let exact = path.join(component);
+        if exact.exists() {
+            path = exact;
+            continue;
+        }
+        let found = std::fs::read_dir(&path).ok().and_then(|entries| {
+            entries.filter_map(|entry| entry.ok()).find(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(component))
+            })
+        });
+        // left to be reported as a per-file error if missing
+        path = found.map_or(exact, |entry| entry.path());
+    }
+    path
+}
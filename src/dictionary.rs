@@ -0,0 +1,59 @@
+use anyhow::{Context, Result, bail};
+use dicom_core::Tag;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// Keywords overriding those of the standard dictionary (e.g. of an older edition)
+#[derive(Debug, Default)]
+struct Aliases {
+    by_tag: HashMap<Tag, String>,
+    by_alias: HashMap<String, Tag>,
+}
+
+/// Keywords loaded with --dictionary, used for the rest of the process
+static ALIASES: RwLock<Option<Aliases>> = RwLock::new(None);
+
+/// Load `TAG KEYWORD` lines (e.g. `(0008,1030) StudyDescription`) from `path`,
+/// replacing the keywords loaded before.
+///
+/// Blank lines and lines starting with `#` are ignored.
+pub(crate) fn load(path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the dictionary {path:?}"))?;
+    let mut aliases = Aliases::default();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let [tag, alias] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+            bail!("{path:?} line {}: expected TAG KEYWORD", i + 1);
+        };
+        let tag: Tag = tag
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{path:?} line {}: invalid tag {tag}", i + 1))?;
+        aliases.by_tag.insert(tag, alias.to_string());
+        aliases.by_alias.insert(alias.to_string(), tag);
+    }
+    *ALIASES.write().unwrap() = Some(aliases);
+    Ok(())
+}
+
+/// Tag of a keyword of the loaded dictionary
+pub(crate) fn tag_by_alias(alias: &str) -> Option<Tag> {
+    ALIASES
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|aliases| aliases.by_alias.get(alias).copied())
+}
+
+/// Keyword of a tag in the loaded dictionary
+pub(crate) fn alias_by_tag(tag: Tag) -> Option<String> {
+    ALIASES
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|aliases| aliases.by_tag.get(&tag).cloned())
+}
@@ -0,0 +1,62 @@
+use crate::{Column, Row};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+
+/// Parse a DICOM DA value (`YYYYMMDD`)
+pub(crate) fn parse_da(value: &str) -> Option<NaiveDate> {
+    // DA values may be padded to even length
+    NaiveDate::parse_from_str(value.trim_end_matches([' ', '\0']), "%Y%m%d").ok()
+}
+
+/// Parse a `YYYYMMDD` date given on the command line
+pub(crate) fn parse_date_arg(s: &str) -> Result<NaiveDate, String> {
+    parse_da(s).ok_or_else(|| format!("Invalid date (expected YYYYMMDD): {s}"))
+}
+
+/// Keep only the files whose date falls within a range
+#[derive(Debug, Clone)]
+pub(crate) struct DateFilter {
+    pub column: Column,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    /// Keep files with a missing or unparseable date
+    pub include_undated: bool,
+}
+
+impl DateFilter {
+    pub fn matches(&self, row: &Row, defaults: &HashMap<Column, String>) -> bool {
+        let Some(date) = parse_da(crate::cell(row, &self.column, defaults)) else {
+            return self.include_undated;
+        };
+        self.from.is_none_or(|from| from <= date) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_dictionary_std::tags;
+
+    #[test]
+    fn test_date_filter() {
+        let column = Column::Tag(tags::STUDY_DATE);
+        let filter = DateFilter {
+            column,
+            from: parse_da("20230101"),
+            to: parse_da("20231231"),
+            include_undated: false,
+        };
+        let row = |date: &str| Row::from([(column, date.to_string())]);
+        let defaults = HashMap::new();
+        assert!(filter.matches(&row("20230101"), &defaults));
+        assert!(filter.matches(&row("20231231 "), &defaults));
+        assert!(!filter.matches(&row("20240101"), &defaults));
+        assert!(!filter.matches(&row(""), &defaults));
+        assert!(!filter.matches(&row("2023-06-01"), &defaults));
+        let filter = DateFilter {
+            include_undated: true,
+            ..filter
+        };
+        assert!(filter.matches(&row(""), &defaults));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{Column, Row, TagExt, TagParseError, tag_to_alias};
+use dicom_core::header::Header;
+use dicom_core::{Tag, VR};
+use dicom_object::InMemDicomObject;
+use dicom_object::mem::InMemElement;
+
+/// Maximum number of nested sequences in an item path
+pub const MAX_SEQUENCE_DEPTH: usize = 8;
+
+/// Path to an element in nested sequence items (e.g. `Seq/0/Sub/1/Tag`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ItemPath {
+    /// Sequences and item indices from the root, valid up to `depth`
+    items: [(Tag, u32); MAX_SEQUENCE_DEPTH],
+    depth: usize,
+    tag: Tag,
+}
+
+impl ItemPath {
+    fn new(items: &[(Tag, u32)], tag: Tag) -> Option<Self> {
+        if items.is_empty() || items.len() > MAX_SEQUENCE_DEPTH {
+            return None;
+        }
+        let mut path = ItemPath {
+            items: [(Tag(0, 0), 0); MAX_SEQUENCE_DEPTH],
+            depth: items.len(),
+            tag,
+        };
+        path.items[..items.len()].copy_from_slice(items);
+        Some(path)
+    }
+
+    fn items(&self) -> &[(Tag, u32)] {
+        &self.items[..self.depth]
+    }
+
+    /// Tag of the element in the innermost item
+    pub fn tag(&self) -> Tag {
+        self.tag
+    }
+
+    /// Top-level sequence the path starts from
+    pub fn root(&self) -> Tag {
+        self.items[0].0
+    }
+
+    /// Parse `Seq/index/.../Tag` with numeric item indices
+    pub(crate) fn parse(s: &str) -> Result<Self, TagParseError> {
+        let err = || TagParseError(s.to_string());
+        let parts: Vec<_> = s.split('/').collect();
+        let Some((tag, parts)) = parts.split_last() else {
+            return Err(err());
+        };
+        if parts.len() % 2 != 0 {
+            return Err(err());
+        }
+        let items = parts
+            .chunks_exact(2)
+            .map(|pair| {
+                let sequence = pair[0].parse::<TagExt>()?.0;
+                let index = pair[1].parse().map_err(|_| err())?;
+                Ok((sequence, index))
+            })
+            .collect::<Result<Vec<_>, TagParseError>>()?;
+        ItemPath::new(&items, tag.parse::<TagExt>()?.0).ok_or_else(err)
+    }
+
+    /// Look up the element at the path
+    pub(crate) fn get<'a>(&self, obj: &'a InMemDicomObject) -> Option<&'a InMemElement> {
+        let mut obj = obj;
+        for (sequence, index) in self.items() {
+            obj = obj.get(*sequence)?.items()?.get(*index as usize)?;
+        }
+        obj.get(self.tag)
+    }
+
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+        for (sequence, index) in self.items() {
+            name.push_str(&format!("{}/{index}/", tag_to_alias(*sequence)));
+        }
+        name.push_str(&tag_to_alias(self.tag));
+        name
+    }
+
+    pub fn spec(&self) -> String {
+        let tag = |tag: Tag| format!("{:04X},{:04X}", tag.group(), tag.element());
+        let mut spec = String::new();
+        for (sequence, index) in self.items() {
+            spec.push_str(&format!("{}/{index}/", tag(*sequence)));
+        }
+        spec.push_str(&tag(self.tag));
+        spec
+    }
+}
+
+/// Add the elements in the items of the top-level sequences of `obj` to `row`,
+/// descending at most `max_depth` levels of sequences.
+///
+/// Only the elements of `vrs` are added unless it is empty.
+pub(crate) fn flatten_sequences(
+    obj: &InMemDicomObject,
+    max_depth: usize,
+    vrs: &[VR],
+    format: &impl Fn(&InMemElement) -> String,
+    row: &mut Row,
+) {
+    let max_depth = max_depth.min(MAX_SEQUENCE_DEPTH);
+    let mut path = Vec::new();
+    for elm in obj.iter() {
+        flatten_element(elm, max_depth, vrs, format, &mut path, row);
+    }
+}
+
+fn flatten_element(
+    elm: &InMemElement,
+    max_depth: usize,
+    vrs: &[VR],
+    format: &impl Fn(&InMemElement) -> String,
+    path: &mut Vec<(Tag, u32)>,
+    row: &mut Row,
+) {
+    let Some(items) = elm.items() else {
+        if !vrs.is_empty() && !vrs.contains(&elm.vr()) {
+            return;
+        }
+        if let Some(item_path) = ItemPath::new(path, elm.tag()) {
+            row.entry(Column::Nested(item_path))
+                .or_insert_with(|| format(elm));
+        }
+        return;
+    };
+    if path.len() >= max_depth {
+        return;
+    }
+    for (i, item) in items.iter().enumerate() {
+        path.push((elm.tag(), i as u32));
+        for elm in item.iter() {
+            flatten_element(elm, max_depth, vrs, format, path, row);
+        }
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_dictionary_std::tags;
+
+    #[test]
+    fn test_item_path() {
+        let path =
+            ItemPath::parse("ReferencedSeriesSequence/0/ReferencedInstanceSequence/1/00081155")
+                .unwrap();
+        assert_eq!(path.root(), tags::REFERENCED_SERIES_SEQUENCE);
+        assert_eq!(
+            path.name(),
+            "ReferencedSeriesSequence/0/ReferencedInstanceSequence/1/ReferencedSOPInstanceUID"
+        );
+        assert_eq!(path.spec(), "0008,1115/0/0008,114A/1/0008,1155");
+        assert_eq!(ItemPath::parse(&path.spec()).unwrap(), path);
+        assert!(
+            ItemPath::parse("ReferencedSeriesSequence/0/ReferencedInstanceSequence/00081155")
+                .is_err()
+        );
+        assert!(
+            ItemPath::parse("ReferencedSeriesSequence/*/ReferencedInstanceSequence/0/00081155")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{Column, extract};
+use anyhow::Result;
+use dicom_core::Tag;
+use dicom_dictionary_std::tags;
+use std::path::Path;
+
+/// Patient module attributes of a file (empty if absent)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatientInfo {
+    pub patient_id: String,
+    pub patient_name: String,
+    pub patient_birth_date: String,
+    pub patient_sex: String,
+    pub patient_age: String,
+}
+
+/// General study attributes of a file (empty if absent)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StudyInfo {
+    pub study_instance_uid: String,
+    pub study_id: String,
+    pub study_date: String,
+    pub study_time: String,
+    pub study_description: String,
+    pub accession_number: String,
+}
+
+/// General series attributes of a file (empty if absent)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeriesInfo {
+    pub series_instance_uid: String,
+    pub series_number: String,
+    pub series_description: String,
+    pub modality: String,
+    pub body_part_examined: String,
+}
+
+/// Read the patient attributes of the file at `path`
+pub fn patient_info(path: &Path) -> Result<PatientInfo> {
+    let [
+        patient_id,
+        patient_name,
+        patient_birth_date,
+        patient_sex,
+        patient_age,
+    ] = extract_values(
+        path,
+        [
+            tags::PATIENT_ID,
+            tags::PATIENT_NAME,
+            tags::PATIENT_BIRTH_DATE,
+            tags::PATIENT_SEX,
+            tags::PATIENT_AGE,
+        ],
+    )?;
+    Ok(PatientInfo {
+        patient_id,
+        patient_name,
+        patient_birth_date,
+        patient_sex,
+        patient_age,
+    })
+}
+
+/// Read the study attributes of the file at `path`
+pub fn study_info(path: &Path) -> Result<StudyInfo> {
+    let [
+        study_instance_uid,
+        study_id,
+        study_date,
+        study_time,
+        study_description,
+        accession_number,
+    ] = extract_values(
+        path,
+        [
+            tags::STUDY_INSTANCE_UID,
+            tags::STUDY_ID,
+            tags::STUDY_DATE,
+            tags::STUDY_TIME,
+            tags::STUDY_DESCRIPTION,
+            tags::ACCESSION_NUMBER,
+        ],
+    )?;
+    Ok(StudyInfo {
+        study_instance_uid,
+        study_id,
+        study_date,
+        study_time,
+        study_description,
+        accession_number,
+    })
+}
+
+/// Read the series attributes of the file at `path`
+pub fn series_info(path: &Path) -> Result<SeriesInfo> {
+    let [
+        series_instance_uid,
+        series_number,
+        series_description,
+        modality,
+        body_part_examined,
+    ] = extract_values(
+        path,
+        [
+            tags::SERIES_INSTANCE_UID,
+            tags::SERIES_NUMBER,
+            tags::SERIES_DESCRIPTION,
+            tags::MODALITY,
+            tags::BODY_PART_EXAMINED,
+        ],
+    )?;
+    Ok(SeriesInfo {
+        series_instance_uid,
+        series_number,
+        series_description,
+        modality,
+        body_part_examined,
+    })
+}
+
+fn extract_values<const N: usize>(path: &Path, tags: [Tag; N]) -> Result<[String; N]> {
+    let columns = tags.map(Column::Tag);
+    let mut row = extract(path, tags::PIXEL_DATA, &columns)?;
+    Ok(columns.map(|column| row.remove(&column).unwrap_or_default()))
+}
@@ -0,0 +1,92 @@
+use crate::{TagExt, TruncateMode, open_dicom, tag_to_alias, truncate_value};
+use anyhow::Result;
+use clap::{Parser, ValueHint};
+use dicom_core::header::Header;
+use dicom_core::value::Value;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Print every element of a file as a readable table
+#[derive(Parser, Default, Debug)]
+pub struct InspectArgs {
+    /// Input file to inspect
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub input: PathBuf,
+
+    /// Read until the specified tag
+    #[clap(long = "until", default_value = "PixelData")]
+    pub read_until: String,
+
+    /// Truncate each value to at most the specified number of characters
+    #[clap(long, default_value_t = 64)]
+    pub max_value_len: usize,
+
+    /// Which part of the values to drop when truncating
+    #[clap(long, value_enum, default_value_t)]
+    pub truncate_mode: TruncateMode,
+
+    /// Also accept files without the 128-byte preamble
+    #[clap(long)]
+    pub no_preamble: bool,
+}
+
+/// A line of the inspection table
+struct Entry {
+    tag: String,
+    alias: String,
+    vr: &'static str,
+    vm: u32,
+    value: String,
+}
+
+impl Entry {
+    fn new<I, P>(elm: &dicom_core::DataElement<I, P>, args: &InspectArgs) -> Self {
+        let value = match elm.value() {
+            Value::Sequence(seq) => format!("({} items)", seq.items().len()),
+            Value::PixelSequence(seq) => format!("({} fragments)", seq.fragments().len()),
+            Value::Primitive(value) => truncate_value(
+                value.to_str().into_owned(),
+                args.max_value_len,
+                args.truncate_mode,
+            ),
+        };
+        Entry {
+            tag: elm.tag().to_string(),
+            alias: tag_to_alias(elm.tag()),
+            vr: elm.vr().to_string(),
+            vm: elm.value().multiplicity(),
+            value,
+        }
+    }
+}
+
+pub(crate) fn main(args: &InspectArgs) -> Result<()> {
+    let read_until = args.read_until.parse::<TagExt>()?.0;
+    let obj = open_dicom(&args.input, Some(read_until), args.no_preamble)?;
+    let mut entries: Vec<_> = obj
+        .meta()
+        .to_element_iter()
+        .map(|elm| Entry::new(&elm, args))
+        .collect();
+    entries.extend(obj.iter().map(|elm| Entry::new(elm, args)));
+    write_table(&entries, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+fn write_table(entries: &[Entry], out: &mut impl Write) -> std::io::Result<()> {
+    let alias_width = entries
+        .iter()
+        .map(|e| e.alias.len())
+        .max()
+        .unwrap_or_default()
+        .max("Alias".len());
+    writeln!(out, "{:<11} {:<alias_width$} VR  VM Value", "Tag", "Alias")?;
+    for e in entries {
+        writeln!(
+            out,
+            "{:<11} {:<alias_width$} {}  {:>2} {}",
+            e.tag, e.alias, e.vr, e.vm, e.value
+        )?;
+    }
+    out.flush()
+}
@@ -0,0 +1,452 @@
+use crate::{Column, DumpOptions, Extractor, ItemIndex, JsonKeyStyle, Row, TagOrder};
+use anyhow::Result;
+use dicom_core::header::Header;
+use dicom_core::value::DataSetSequence;
+use dicom_core::{DataElement, Tag, VR};
+use dicom_object::InMemDicomObject;
+use rayon::prelude::*;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How the output was produced, written as `_meta` of each record with --embed-provenance
+#[derive(Debug, Clone)]
+pub(crate) struct Provenance {
+    timestamp: String,
+    command: String,
+}
+
+impl Provenance {
+    pub fn new() -> Self {
+        Provenance {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    /// The `_meta` object of the records with the columns
+    fn to_json(&self, columns: &[Column]) -> Value {
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "timestamp": self.timestamp,
+            "command": self.command,
+            "tags": columns.iter().map(Column::spec).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Convert an extracted row into a JSON object keyed by column name
+/// (or by the spec without commas with `JsonKeyStyle::Hex`, e.g. `00081140/0/00081155`).
+///
+/// Values collected from every item of a sequence are written as an array with an element per item
+/// (empty if the item lacks the element).
+/// Values of numeric VRs are written as numbers (or arrays of numbers if multi-valued)
+/// unless they fail to parse, and as `null` if empty, also in the arrays of item values.
+pub(crate) fn row_to_json(
+    path: &Path,
+    columns: &[Column],
+    row: &Row,
+    defaults: &HashMap<Column, String>,
+    options: &DumpOptions,
+) -> Map<String, Value> {
+    let mut object = Map::new();
+    object.insert(
+        "path".to_string(),
+        Value::String(path.to_string_lossy().into_owned()),
+    );
+    for column in columns {
+        let value = crate::cell(row, column, defaults);
+        let value = match column {
+            Column::Item {
+                sequence,
+                index: ItemIndex::All,
+                tag,
+            } => {
+                let separator = options.value_separator.as_deref().unwrap_or("\\");
+                let numeric = crate::is_numeric_column(&Column::Tag(*tag));
+                let mut values = item_values(row, *sequence, *tag);
+                // the default of the column
+                if values.is_empty() && !value.is_empty() {
+                    values.push(value);
+                }
+                let values = values.into_iter().map(|v| {
+                    if numeric {
+                        numeric_value(v, separator)
+                    } else {
+                        Value::String(v.to_string())
+                    }
+                });
+                Value::Array(values.collect())
+            }
+            _ if crate::is_numeric_column(column) => {
+                let separator = options.value_separator.as_deref().unwrap_or("\\");
+                numeric_value(value, separator)
+            }
+            _ => Value::String(value.to_string()),
+        };
+        let key = match options.json_key_style {
+            JsonKeyStyle::Alias => options.column_name(column),
+            JsonKeyStyle::Hex => column.spec().replace(',', ""),
+        };
+        object.insert(key, value);
+    }
+    object
+}
+
+/// Values of `tag` in each item of `sequence`, extracted along with the `*` item column
+fn item_values(row: &Row, sequence: Tag, tag: Tag) -> Vec<&str> {
+    let n_items = row
+        .get(&Column::ItemCount(sequence))
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+    (0..n_items)
+        .map(|i| {
+            let column = Column::Item {
+                sequence,
+                index: ItemIndex::Index(i),
+                tag,
+            };
+            row.get(&column).map(String::as_str).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Parse a (possibly multi-valued) numeric value, falling back to the string itself
+fn numeric_value(value: &str, separator: &str) -> Value {
+    if value.trim().is_empty() {
+        return Value::Null;
+    }
+    let parse = |v: &str| {
+        let v = v.trim();
+        match v.parse::<i64>() {
+            Ok(n) => Some(Value::from(n)),
+            Err(_) => v
+                .parse::<f64>()
+                .ok()
+                .and_then(|n| serde_json::Number::from_f64(n).map(Value::Number)),
+        }
+    };
+    let numbers: Option<Vec<_>> = value.split(separator).map(parse).collect();
+    match numbers {
+        Some(mut numbers) if numbers.len() == 1 => numbers.pop().unwrap(),
+        Some(numbers) => Value::Array(numbers),
+        None => Value::String(value.to_string()),
+    }
+}
+
+/// Write all rows as a JSON array of objects
+pub(crate) fn write_json(
+    output: &Path,
+    rows: &[(PathBuf, Row)],
+    columns: &[Column],
+    defaults: &HashMap<Column, String>,
+    options: &DumpOptions,
+) -> Result<()> {
+    let meta = options.provenance.as_ref().map(|p| p.to_json(columns));
+    let records: Vec<_> = rows
+        .iter()
+        .map(|(path, row)| {
+            let mut object = row_to_json(path, columns, row, defaults, options);
+            if let Some(meta) = &meta {
+                object.insert("_meta".to_string(), meta.clone());
+            }
+            Value::Object(object)
+        })
+        .collect();
+    let mut writer = BufWriter::new(File::create(output)?);
+    serde_json::to_writer_pretty(&mut writer, &records)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write all rows as newline-delimited JSON
+pub(crate) fn write_ndjson(
+    output: &Path,
+    rows: &[(PathBuf, Row)],
+    columns: &[Column],
+    defaults: &HashMap<Column, String>,
+    options: &DumpOptions,
+) -> Result<()> {
+    let meta = options.provenance.as_ref().map(|p| p.to_json(columns));
+    let mut writer = BufWriter::new(File::create(output)?);
+    for (path, row) in rows {
+        let mut object = row_to_json(path, columns, row, defaults, options);
+        if let Some(meta) = &meta {
+            object.insert("_meta".to_string(), meta.clone());
+        }
+        serde_json::to_writer(&mut writer, &object)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Pick the elements of the requested columns (or every element in all-tags mode).
+///
+/// Values in sequence items are written along with their whole sequence,
+/// nested down to `max_json_depth`.
+fn dicom_json_object(
+    input: &Path,
+    read_until: Tag,
+    columns: &[Column],
+    options: &DumpOptions,
+) -> Result<InMemDicomObject> {
+    let obj = crate::open_dicom(input, Some(read_until), options.no_preamble)?;
+    let picked = pick_elements(obj.into_inner(), columns, options);
+    Ok(match options.max_json_depth {
+        Some(max_depth) => limit_depth(&picked, max_depth),
+        None => picked,
+    })
+}
+
+fn pick_elements(
+    obj: InMemDicomObject,
+    columns: &[Column],
+    options: &DumpOptions,
+) -> InMemDicomObject {
+    if options.all_tags {
+        return obj;
+    }
+    let mut picked = InMemDicomObject::new_empty();
+    for column in columns {
+        let tag = match column {
+            Column::Tag(tag) => *tag,
+            Column::Item { sequence, .. } => *sequence,
+            Column::Nested(path) => path.root(),
+            Column::Component { tag, .. } => *tag,
+            Column::Coalesced(tag) => match crate::retired::equivalent(*tag) {
+                Some(equivalent) if obj.get(*tag).is_none() => equivalent,
+                _ => *tag,
+            },
+            Column::Derived(_) | Column::Length(_) | Column::Cohort | Column::ItemCount(_) => {
+                continue;
+            }
+            Column::Pattern { pattern, .. } => {
+                for elm in obj.iter().filter(|elm| pattern.matches(elm.tag())) {
+                    picked.put(elm.clone());
+                }
+                continue;
+            }
+        };
+        if let Some(elm) = obj.get(tag) {
+            picked.put(elm.clone());
+        }
+    }
+    picked
+}
+
+/// Copy `obj` with the items of the sequences nested more than `max_depth` levels left out
+fn limit_depth(obj: &InMemDicomObject, max_depth: usize) -> InMemDicomObject {
+    InMemDicomObject::from_element_iter(obj.iter().map(|elm| match elm.items() {
+        Some(items) if elm.vr() == VR::SQ => {
+            let items = if max_depth == 0 {
+                Vec::new()
+            } else {
+                items
+                    .iter()
+                    .map(|item| limit_depth(item, max_depth - 1))
+                    .collect()
+            };
+            DataElement::new(elm.tag(), VR::SQ, DataSetSequence::from(items))
+        }
+        _ => elm.clone(),
+    }))
+}
+
+/// Write the files as a DICOM JSON Model array.
+///
+/// The files are read again since the extracted rows only hold formatted strings.
+pub(crate) fn write_dicom_json(
+    mut output: impl Write,
+    inputs: &[&Path],
+    read_until: Tag,
+    columns: &[Column],
+    options: &DumpOptions,
+) -> Result<()> {
+    let objects: Vec<_> = inputs
+        .par_iter()
+        .filter_map(
+            |input| match dicom_json_object(input, read_until, columns, options) {
+                Ok(obj) => Some(obj),
+                Err(e) => {
+                    eprintln!("Skipping {:?}: {e:#}", input);
+                    None
+                }
+            },
+        )
+        .collect();
+    let mut value = dicom_json::to_value(objects)?;
+    if let Some(max_bytes) = options.base64_binary {
+        drop_large_binary(&mut value, max_bytes);
+    }
+    serde_json::to_writer_pretty(&mut output, &value)?;
+    writeln!(output)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Remove `InlineBinary` values of more than `max_bytes` bytes, leaving the attributes without a value
+fn drop_large_binary(value: &mut Value, max_bytes: usize) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::String(binary)) = object.get("InlineBinary") {
+                let padding = binary.bytes().rev().take_while(|&b| b == b'=').count();
+                if binary.len() / 4 * 3 - padding > max_bytes {
+                    object.remove("InlineBinary");
+                }
+            }
+            object
+                .values_mut()
+                .for_each(|value| drop_large_binary(value, max_bytes));
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| drop_large_binary(value, max_bytes)),
+        _ => {}
+    }
+}
+
+/// Read `{"path": "..."}` objects line by line and write the extracted values as NDJSON.
+///
+/// Each record is flushed as soon as it is written.
+/// Malformed lines are reported to stderr and skipped,
+/// and files which fail to be processed are written with an `error` field.
+pub(crate) fn stream_ndjson(
+    input: impl BufRead,
+    mut output: impl Write,
+    extractor: &Extractor,
+    defaults: &HashMap<Column, String>,
+) -> Result<()> {
+    let columns = extractor.columns();
+    let options = &extractor.options;
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let path = match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Object(object)) => match object.get("path") {
+                Some(Value::String(path)) => path.clone(),
+                _ => {
+                    eprintln!("Skipping line {}: missing \"path\" string", i + 1);
+                    continue;
+                }
+            },
+            Ok(_) => {
+                eprintln!("Skipping line {}: not a JSON object", i + 1);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Skipping line {}: {e}", i + 1);
+                continue;
+            }
+        };
+        let path = Path::new(&path);
+        let mut record_columns = columns.to_vec();
+        let mut record = match extractor.extract(path) {
+            Ok(row) => {
+                let mut all_columns = crate::expand_patterns(columns, std::iter::once(&row));
+                if options.all_tags {
+                    let union = crate::union_columns(
+                        std::iter::once(&row),
+                        &all_columns,
+                        TagOrder::GroupElement,
+                    );
+                    all_columns.extend(union);
+                    if options.show_bytes {
+                        all_columns = crate::with_byte_lengths(&all_columns);
+                    }
+                }
+                let object = row_to_json(path, &all_columns, &row, defaults, options);
+                record_columns = all_columns;
+                object
+            }
+            Err(e) => {
+                let mut object = Map::new();
+                object.insert(
+                    "path".to_string(),
+                    Value::String(path.to_string_lossy().into_owned()),
+                );
+                object.insert("error".to_string(), Value::String(format!("{e:#}")));
+                object
+            }
+        };
+        if let Some(provenance) = &options.provenance {
+            record.insert("_meta".to_string(), provenance.to_json(&record_columns));
+        }
+        serde_json::to_writer(&mut output, &record)?;
+        writeln!(output)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_dictionary_std::tags;
+    use serde_json::json;
+
+    #[test]
+    fn test_numeric_value() {
+        assert_eq!(numeric_value("512", "\\"), json!(512));
+        assert_eq!(numeric_value(" 0.5 ", "\\"), json!(0.5));
+        assert_eq!(numeric_value("-1\\2.5", "\\"), json!([-1, 2.5]));
+        assert_eq!(numeric_value("1|2", "|"), json!([1, 2]));
+        assert_eq!(numeric_value("", "\\"), Value::Null);
+        assert_eq!(numeric_value(" ", "\\"), Value::Null);
+        assert_eq!(numeric_value("1\\n/a", "\\"), json!("1\\n/a"));
+    }
+
+    #[test]
+    fn test_drop_large_binary() {
+        let mut value = json!([{
+            "00091001": {"vr": "OB", "InlineBinary": "AAECAw=="},
+            "00091002": {"vr": "OB", "InlineBinary": "AAE="},
+        }]);
+        drop_large_binary(&mut value, 2);
+        assert_eq!(
+            value,
+            json!([{
+                "00091001": {"vr": "OB"},
+                "00091002": {"vr": "OB", "InlineBinary": "AAE="},
+            }])
+        );
+    }
+
+    #[test]
+    fn test_limit_depth() {
+        let sequence = |items| {
+            DataElement::new(
+                tags::REFERENCED_IMAGE_SEQUENCE,
+                VR::SQ,
+                DataSetSequence::from(items),
+            )
+        };
+        let leaf = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::REFERENCED_SOP_INSTANCE_UID,
+            VR::UI,
+            "1.2.3",
+        )]);
+        let inner = InMemDicomObject::from_element_iter([sequence(vec![leaf])]);
+        let obj = InMemDicomObject::from_element_iter([sequence(vec![inner])]);
+        let depth = |obj: &InMemDicomObject| {
+            let mut depth = 0;
+            let mut items = obj.get(tags::REFERENCED_IMAGE_SEQUENCE).unwrap().items();
+            while let Some([item]) = items {
+                depth += 1;
+                items = item
+                    .get(tags::REFERENCED_IMAGE_SEQUENCE)
+                    .and_then(|elm| elm.items());
+            }
+            depth
+        };
+        assert_eq!(depth(&obj), 2);
+        assert_eq!(depth(&limit_depth(&obj, 2)), 2);
+        assert_eq!(depth(&limit_depth(&obj, 1)), 1);
+        assert_eq!(depth(&limit_depth(&obj, 0)), 0);
+    }
+}
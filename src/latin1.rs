@@ -0,0 +1,58 @@
+use std::io::{self, Write};
+
+/// Writer transcoding UTF-8 into ISO-8859-1 (Latin-1),
+/// replacing the characters which are not representable with `?`
+pub(crate) struct Latin1Writer<W: Write> {
+    inner: W,
+    /// Bytes of a character split across writes
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Latin1Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Latin1Writer {
+            inner,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for Latin1Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid_len = match std::str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            // an incomplete character at the end
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let valid = std::str::from_utf8(&self.pending[..valid_len]).unwrap();
+        let encoded: Vec<u8> = valid
+            .chars()
+            .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+            .collect();
+        self.inner.write_all(&encoded)?;
+        self.pending.drain(..valid_len);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latin1_writer() {
+        let mut writer = Latin1Writer::new(Vec::new());
+        let text = "Müller^José,山田\n".as_bytes();
+        // split in the middle of `ü`
+        writer.write_all(&text[..2]).unwrap();
+        writer.write_all(&text[2..]).unwrap();
+        assert_eq!(writer.inner, b"M\xFCller^Jos\xE9,??\n");
+        assert!(writer.write_all(b"\xFF").is_err());
+    }
+}
@@ -1,52 +1,590 @@
-use anyhow::{Result, bail};
-use clap::{CommandFactory, Parser, ValueHint};
+use anyhow::{Context, Result, anyhow, bail};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, ValueHint};
 use clap_complete::Shell;
 use clap_complete::{Generator, generate};
-use dicom_core::{DataDictionary, Tag, dictionary::DataDictionaryEntry};
-use dicom_object::StandardDataDictionary;
-use log::{debug, info};
+use dicom_core::dictionary::{DataDictionaryEntry, TagRange, VirtualVr};
+use dicom_core::header::{HasLength, Header};
+use dicom_core::{DataDictionary, PrimitiveValue, Tag, VR};
+use dicom_dictionary_std::tags;
+use dicom_object::file::ReadPreamble;
+use dicom_object::mem::InMemElement;
+use dicom_object::{DefaultDicomObject, OpenFileOptions, StandardDataDictionary};
+use log::{debug, info, warn};
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+mod aggregate;
+mod archive;
+mod cohort;
+mod conformance;
+mod derived;
+mod dicomdir;
+mod dictionary;
+mod filter;
+mod flatten;
+mod info;
+mod inspect;
+mod json;
+mod latin1;
+mod lookup;
+mod md5;
+mod partial;
+mod pattern;
+mod person_name;
+mod pixel;
+mod presets;
+mod profile;
+mod retired;
+mod selftest;
+mod split;
+mod summary;
+mod table;
+mod tagfile;
+mod value_filter;
+mod watch;
+mod xlsx;
+pub use derived::Derived;
+use derived::DerivedContext;
+pub use flatten::ItemPath;
+pub use info::{PatientInfo, SeriesInfo, StudyInfo, patient_info, series_info, study_info};
+pub use inspect::InspectArgs;
+pub use pattern::TagPattern;
+pub use person_name::NameComponent;
+pub use pixel::PixelFrames;
+pub use presets::Preset;
+pub use tagfile::TagFileFormat;
 
 /// Dump DICOM tags to CSV
 #[derive(Parser, Default, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     /// Input file to process
-    #[clap(required=true, num_args=1.., value_hint = ValueHint::AnyPath)]
+    #[clap(required_unless_present_any = ["input_list", "ndjson", "write_tag_file"], num_args=1.., value_hint = ValueHint::AnyPath)]
     pub input: Vec<PathBuf>,
 
-    /// Tags to extract
+    /// Read input paths from the specified file (one path per line)
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub input_list: Vec<PathBuf>,
+
+    /// Tags to extract. Multiple tags can be separated by commas
+    /// (use the keyword or `ggggeeee` form in such lists).
+    /// A tag followed by `=VALUE` falls back to the value when it is absent or empty
     #[clap(short, long)]
     pub tag: Vec<String>,
 
-    /// Load tags from the specified file
+    /// Add a curated list of tags for the given context
+    #[clap(long, value_enum)]
+    pub preset: Vec<Preset>,
+
+    /// Prepend the PatientID, StudyInstanceUID, and SeriesInstanceUID columns unless already requested
+    #[clap(long)]
+    pub hierarchy: bool,
+
+    /// Label the files found in an input in a Cohort column (e.g. `dirA=control`)
+    #[clap(long, value_name = "INPUT=LABEL")]
+    pub label: Vec<String>,
+
+    /// Label the files of each input directory with the name of the directory
+    /// in a Cohort column (unless given with --label)
+    #[clap(long)]
+    pub label_inputs: bool,
+
+    /// Code sequence to extract the CodeMeaning of the first item from
+    #[clap(long)]
+    pub code: Vec<String>,
+
+    /// Also extract the CodeValue of the code sequences given by `--code`
+    #[clap(long, requires = "code")]
+    pub code_value: bool,
+
+    /// Extract all top-level elements in addition to the specified tags
+    #[clap(short, long)]
+    pub all_tags: bool,
+
+    /// Keep only the specified number of columns found in all-tags mode,
+    /// choosing those present in the most files
+    #[clap(long, value_name = "N", requires = "all_tags")]
+    pub columns_limit: Option<usize>,
+
+    /// Only extract the elements of the given VRs in all-tags mode (e.g. `PN,UI`)
+    #[clap(long, value_name = "VR", value_delimiter = ',', value_parser = parse_vr, requires = "all_tags")]
+    pub tag_vr: Vec<VR>,
+
+    /// Also extract the elements in sequence items in all-tags mode (e.g. `Seq/0/Sub/1/Tag`)
+    #[clap(long, requires = "all_tags")]
+    pub flatten_sequences: bool,
+
+    /// Maximum number of nested sequences to descend into with --flatten-sequences (at most 8)
+    #[clap(long, requires = "flatten_sequences")]
+    pub max_sequence_depth: Option<usize>,
+
+    /// Order of the columns found in all-tags mode
+    #[clap(long, value_enum, default_value_t, requires = "all_tags")]
+    pub tag_order: TagOrder,
+
+    /// Fallback value for a tag when it is absent or empty (e.g. `Modality=UNKNOWN`)
+    #[clap(long, value_name = "TAG=VALUE")]
+    pub default: Vec<String>,
+
+    /// Pipe the values of a tag through a shell command, run once per distinct value
+    /// (e.g. `PatientID:./pseudonymize.sh`)
+    #[clap(long, value_name = "TAG:COMMAND")]
+    pub value_filter: Vec<String>,
+
+    /// Replace the values of a tag with the labels in a two-column `VALUE,LABEL` CSV file,
+    /// keeping the values not listed (e.g. `BodyPartExamined:body_parts.csv`)
+    #[clap(long, value_name = "TAG:FILE")]
+    pub map: Vec<String>,
+
+    /// Load tags from the specified file (one tag per line, `#` starts a comment)
     #[clap(short='f', long, value_hint = ValueHint::FilePath)]
     pub tag_file: Vec<PathBuf>,
 
+    /// Format of the tag files (detected by the extension if not specified)
+    #[clap(long, value_enum)]
+    pub tag_file_format: Option<TagFileFormat>,
+
+    /// Write the resolved tags to the specified file in the `--tag-file` format and exit
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub write_tag_file: Option<PathBuf>,
+
+    /// Keywords overriding those of the standard dictionary (e.g. of an older edition)
+    /// for parsing the tags and naming the columns, as `TAG KEYWORD` lines
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub dictionary: Option<PathBuf>,
+
     /// Read until the specified tag
     #[clap(long = "until", default_value = "PixelData")]
     pub read_until: String,
 
+    /// Separator used to join multi-valued elements (backslash if not specified)
+    #[clap(long)]
+    pub value_separator: Option<String>,
+
+    /// Truncate each value to at most the specified number of characters
+    #[clap(long)]
+    pub max_value_len: Option<usize>,
+
+    /// Which part of the values to drop when truncating
+    #[clap(long, value_enum, default_value_t, requires = "max_value_len")]
+    pub truncate_mode: TruncateMode,
+
+    /// Split the values of a multi-valued element into a column per value
+    /// (e.g. `ImageType_1`, `ImageType_2`, ...)
+    #[clap(long, value_name = "TAG", conflicts_with = "ndjson")]
+    pub split_multivalue: Vec<String>,
+
+    /// Maximum number of columns per element split by --split-multivalue
+    #[clap(long, requires = "split_multivalue")]
+    pub max_split_values: Option<usize>,
+
+    /// Write the values of binary elements (OB, OW, UN, ...) as base64 if at most the given number of bytes,
+    /// or as a placeholder with the length otherwise
+    #[clap(long, value_name = "MAX_BYTES")]
+    pub base64_binary: Option<usize>,
+
+    /// Reformat person names (PN) from `Family^Given^...` into space-separated components
+    #[clap(long)]
+    pub normalize_person_names: bool,
+
+    /// Order of the person name components [default: given,family]
+    #[clap(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        requires = "normalize_person_names"
+    )]
+    pub name_order: Vec<NameComponent>,
+
+    /// Remove inputs referring to the same file (e.g. given directly and via a directory)
+    #[clap(long)]
+    pub dedupe_inputs: bool,
+
+    /// Search input directories recursively
+    #[clap(short, long)]
+    pub recursive: bool,
+
+    /// Maximum depth of the recursive search (1 searches the input directory only)
+    #[clap(long, requires = "recursive")]
+    pub max_depth: Option<usize>,
+
+    /// Descend into symlinked directories in the recursive search, skipping the
+    /// directories already searched so that symlink loops end
+    #[clap(long, requires = "recursive")]
+    pub follow_symlinks: bool,
+
+    /// Strip leading and trailing whitespace and null padding from string values (default)
+    #[clap(long, overrides_with = "no_trim")]
+    pub trim: bool,
+
+    /// Keep the padding of string values as stored
+    #[clap(long)]
+    pub no_trim: bool,
+
+    /// Also accept files without the 128-byte preamble or the DICM magic code
+    #[clap(long)]
+    pub no_preamble: bool,
+
+    /// Read at most the first N bytes of each file for fast header scans,
+    /// leaving the tags not reached within the limit empty
+    #[clap(long, value_name = "N", conflicts_with = "no_preamble")]
+    pub max_read_bytes: Option<u64>,
+
+    /// Check the DICM magic code of the input files before reading them,
+    /// skipping the files without it instead of reporting them as errors
+    #[clap(long, conflicts_with = "no_preamble")]
+    pub require_dicm: bool,
+
+    /// Add a `<alias>_bytes` column with the value length in bytes after each tag column
+    #[clap(long)]
+    pub show_bytes: bool,
+
+    /// Add a `<alias>_items` column with the number of items after each sequence tag column
+    #[clap(long)]
+    pub show_sequence_count: bool,
+
+    /// Add a column with the VR encoding and byte order of each file
+    #[clap(long)]
+    pub show_encoding: bool,
+
+    /// Add PhotometricInterpretation and `@has_icc_profile` columns to find color images
+    #[clap(long)]
+    pub show_color: bool,
+
+    /// Add InstanceNumber and `@series_index` columns with the 0-based position
+    /// of each file in its series sorted by InstanceNumber
+    #[clap(long, conflicts_with_all = ["max_buffered", "watch"])]
+    pub compute_series_index: bool,
+
+    /// Add a column with the MD5 of the other extracted values, in a canonical order
+    /// of the tags, to find files with the same metadata
+    #[clap(long)]
+    pub metadata_hash: bool,
+
+    /// Add columns with the min, max, and mean of the stored pixel values.
+    ///
+    /// This reads the whole pixel data regardless of `--until`, which is expensive.
+    /// Only native (uncompressed) pixel data is supported.
+    #[clap(long)]
+    pub pixel_stats: bool,
+
+    /// Frames to compute the pixel statistics over
+    #[clap(long, value_enum, default_value_t)]
+    pub pixel_stats_frames: PixelFrames,
+
     /// The number of threads to use
     #[clap(short, long)]
     pub jobs: Option<usize>,
 
+    /// Report a panic while processing a file as an error of the file and continue (default)
+    #[clap(long, overrides_with = "abort_on_panic")]
+    pub continue_on_panic: bool,
+
+    /// Abort on a panic while processing a file, e.g. to get the backtrace for debugging
+    #[clap(long, overrides_with = "continue_on_panic")]
+    pub abort_on_panic: bool,
+
+    /// Stop with an error once more than N files have failed to be processed,
+    /// e.g. when given a wrong directory
+    #[clap(long, value_name = "N")]
+    pub max_errors: Option<usize>,
+
     /// Output file to write to
     #[clap(last=true, value_hint = ValueHint::FilePath)]
     pub output: Option<PathBuf>,
 
+    /// Append rows to the output file instead of overwriting it (the header is written only to a new file)
+    #[clap(long, requires = "output")]
+    pub append: bool,
+
+    /// Do not write the header row of the CSV output (even to a new file with --append)
+    #[clap(long, conflicts_with_all = ["value_counts", "summary", "aggregate_by", "transpose", "conformance", "list_errors_only"])]
+    pub no_header: bool,
+
+    /// Skip files smaller than the specified size in bytes (K, M, and G suffixes are accepted)
+    #[clap(long, value_parser = parse_size)]
+    pub min_file_size: Option<u64>,
+
+    /// Skip files larger than the specified size in bytes (K, M, and G suffixes are accepted)
+    #[clap(long, value_parser = parse_size)]
+    pub max_file_size: Option<u64>,
+
+    /// Process a random subset of the files with the given fraction (e.g. 0.1 for 10%)
+    #[clap(long, value_name = "P", value_parser = parse_fraction, conflicts_with = "watch")]
+    pub sample_random: Option<f64>,
+
+    /// Seed of the random sampling, for selecting the same files again
+    /// (a random seed is used and reported if not given)
+    #[clap(long, requires = "sample_random")]
+    pub seed: Option<u64>,
+
+    /// Skip files already listed in the FileName column of a previous output
+    /// (refused if a listed name matches several input files)
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pub resume: Option<PathBuf>,
+
+    /// Write a UTF-8 byte order mark at the start of the output file (e.g. for Excel)
+    #[clap(long)]
+    pub utf8_bom: bool,
+
+    /// Character encoding of the CSV output (JSON outputs are always UTF-8)
+    #[clap(long, value_enum, default_value_t)]
+    pub output_encoding: OutputEncoding,
+
+    /// When the fields of the CSV output are quoted
+    #[clap(long, value_enum, default_value_t)]
+    pub csv_quoting: CsvQuoting,
+
+    /// Line terminator of the CSV output (`crlf` as in RFC 4180)
+    #[clap(long, value_enum, default_value_t)]
+    pub csv_terminator: CsvTerminator,
+
+    /// How NUL bytes embedded in the values are written
+    #[clap(long, value_enum, default_value_t)]
+    pub output_null_bytes_policy: NullBytesPolicy,
+
+    /// Write `#` comment lines with the version, command line, and timestamp before the header
+    #[clap(long)]
+    pub csv_comment: bool,
+
+    /// Output only the files that failed to be processed along with the error
+    #[clap(long)]
+    pub list_errors_only: bool,
+
+    /// Tag of the date to filter the files by (e.g. StudyDate)
+    #[clap(long, value_name = "TAG")]
+    pub date_tag: Option<String>,
+
+    /// Only include files dated on or after the specified date (YYYYMMDD)
+    #[clap(long, value_parser = filter::parse_date_arg, requires = "date_tag")]
+    pub date_from: Option<chrono::NaiveDate>,
+
+    /// Only include files dated on or before the specified date (YYYYMMDD)
+    #[clap(long, value_parser = filter::parse_date_arg, requires = "date_tag")]
+    pub date_to: Option<chrono::NaiveDate>,
+
+    /// Also include files whose date is missing or unparseable
+    #[clap(long, requires = "date_tag")]
+    pub include_undated: bool,
+
+    /// Write the rows as soon as they are extracted, holding at most the specified number in memory.
+    /// Rows are written in the order of completion
+    #[clap(long, conflicts_with_all = ["all_tags", "value_counts", "list_errors_only", "json_output", "ndjson_output", "split_multivalue"])]
+    pub max_buffered: Option<usize>,
+
+    /// Keep running and write rows for the `.dcm` files added to the input directories
+    #[clap(long, conflicts_with_all = ["all_tags", "value_counts", "list_errors_only", "json_output", "ndjson_output", "split_multivalue", "max_buffered", "conformance", "aggregate_by", "transpose"])]
+    pub watch: bool,
+
+    /// Seconds between scans of the input directories with --watch [default: 2]
+    #[clap(long, value_name = "SECONDS", requires = "watch")]
+    pub watch_interval: Option<f64>,
+
+    /// Report the files missing a value of the tags required for their SOP class
+    /// by a JSON profile (`{"<SOP class UID>": ["PatientID", ...]}`)
+    #[clap(long, value_name = "PROFILE", value_hint = ValueHint::FilePath, conflicts_with_all = ["value_counts", "list_errors_only", "max_buffered"])]
+    pub conformance: Option<PathBuf>,
+
+    /// Output format
+    #[clap(long, value_enum, default_value_t, conflicts_with = "csv_comment")]
+    pub format: OutputFormat,
+
+    /// Output the number of files for each distinct value of the tags as (Tag, Value, Count) rows
+    #[clap(long, conflicts_with = "list_errors_only")]
+    pub value_counts: bool,
+
+    /// Output the minimum and maximum length (in characters) of the non-empty values
+    /// of the tags as (Tag, MinLen, MaxLen, NonEmptyCount) rows, e.g. to size database columns
+    #[clap(long, conflicts_with_all = ["value_counts", "list_errors_only"])]
+    pub length_stats: bool,
+
+    /// Output a single row with the sorted distinct non-empty values of each tag
+    /// across all the files, joined like multi-valued elements
+    #[clap(long, conflicts_with_all = ["value_counts", "length_stats", "list_errors_only"])]
+    pub distinct_values: bool,
+
+    /// Write a row per distinct value of the tag (e.g. SeriesInstanceUID) with the number of files
+    /// and the values of the other tags combined by the rules given by --aggregate
+    #[clap(long, value_name = "TAG", conflicts_with_all = ["value_counts", "transpose", "max_buffered", "list_errors_only", "format", "append"])]
+    pub aggregate_by: Option<String>,
+
+    /// How the values of a tag are combined with --aggregate-by (`first` if not given):
+    /// `TAG=first`, `min`, `max`, `count`, or `list` (can be repeated)
+    #[clap(long, value_name = "TAG=RULE", requires = "aggregate_by")]
+    pub aggregate: Vec<String>,
+
+    /// Write the rows into a CSV file per value of the tag (e.g. `<output>/CT.csv`),
+    /// with the output as the directory
+    #[clap(long, value_name = "TAG", requires = "output", conflicts_with_all = ["value_counts", "aggregate_by", "summary", "transpose", "max_buffered", "watch", "conformance", "list_errors_only", "format", "append"])]
+    pub split_by: Option<String>,
+
+    /// Replace the characters not allowed in file names with `_` and truncate long values
+    /// in the --split-by file names, listing the original values in `filenames.csv`
+    #[clap(long, requires = "split_by")]
+    pub replace_separators_in_filename: bool,
+
+    /// Output the number of files for each value of the tag (Modality if no tag is given)
+    #[clap(long, value_name = "TAG", num_args = 0..=1, require_equals = true, default_missing_value = "Modality", conflicts_with_all = ["value_counts", "aggregate_by", "transpose", "max_buffered", "watch", "conformance", "list_errors_only", "format"])]
+    pub summary: Option<String>,
+
+    /// Break down the --summary counts by the month of StudyDate
+    #[clap(long, requires = "summary")]
+    pub summary_by_month: bool,
+
+    /// Drop the columns without a value in any file
+    #[clap(long, conflicts_with_all = ["max_buffered", "watch"])]
+    pub exclude_empty_columns: bool,
+
+    /// Skip the files without a value (or a default) for any of the columns
+    #[clap(long, conflicts_with_all = ["max_buffered", "watch"])]
+    pub skip_empty_rows: bool,
+
+    /// Sort the columns (after FileName) by name
+    #[clap(long)]
+    pub sort_columns: bool,
+
+    /// Write the tags as rows and the files as columns (only practical for a few files)
+    #[clap(long, conflicts_with_all = ["append", "max_buffered", "value_counts", "format"])]
+    pub transpose: bool,
+
+    /// Compare values case-insensitively when counting them with --value-counts
+    /// (or collecting them with --distinct-values, or grouping them with --split-by)
+    #[clap(long)]
+    pub case_fold_values: bool,
+
+    /// Read `{"path": ...}` NDJSON records from stdin and write the extracted values as NDJSON
+    #[clap(long, conflicts_with_all = ["input", "input_list"])]
+    pub ndjson: bool,
+
+    /// Also write the output as a JSON array to the specified file
+    #[clap(long, value_hint = ValueHint::FilePath, conflicts_with_all = ["value_counts", "distinct_values", "length_stats", "list_errors_only", "format", "aggregate_by", "summary", "conformance"])]
+    pub json_output: Option<PathBuf>,
+
+    /// Also write the output as newline-delimited JSON to the specified file
+    #[clap(long, value_hint = ValueHint::FilePath, conflicts_with_all = ["value_counts", "distinct_values", "length_stats", "list_errors_only", "format", "aggregate_by", "summary", "conformance"])]
+    pub ndjson_output: Option<PathBuf>,
+
+    /// Add the szkdcm version, the timestamp, the command, and the tags to each JSON record as `_meta`
+    #[clap(long)]
+    pub embed_provenance: bool,
+
+    /// Keys of the objects in the JSON and NDJSON output
+    #[clap(long, value_enum, default_value_t)]
+    pub json_key_style: JsonKeyStyle,
+
+    /// Maximum depth of nested sequences written with their items in the DICOM JSON output
+    /// (1 for the items of top-level sequences only); deeper sequences are written empty
+    #[clap(long, value_name = "N")]
+    pub max_json_depth: Option<usize>,
+
+    /// Print the time spent in each stage (enumeration, extraction, and writing) to stderr
+    #[clap(long)]
+    pub profile: bool,
+
     /// Generate shell completions
     #[clap(long)]
     pub complete: Option<Shell>,
 }
 
+/// Format of the main output
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One row per file with the FileName and the requested columns
+    #[default]
+    Csv,
+    /// DICOM JSON Model (PS3.18) array with one object per file
+    DicomJson,
+    /// Excel workbook with the same table as the CSV output
+    Xlsx,
+    /// Aligned plain text table, fitted to the width of the terminal
+    Table,
+}
+
+/// Character encoding of the CSV output
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// UTF-8
+    #[default]
+    Utf8,
+    /// ISO-8859-1, with the characters not representable replaced with `?`
+    Latin1,
+}
+
+/// Quoting of the CSV fields
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CsvQuoting {
+    /// Only the fields containing a quote, a comma, or a line break
+    #[default]
+    Necessary,
+    /// Every field
+    Always,
+    /// No field, even if the output cannot be parsed back
+    Never,
+}
+
+/// Line terminator of the CSV records
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CsvTerminator {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+/// Handling of NUL bytes in the values, which many CSV readers do not expect
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NullBytesPolicy {
+    /// Remove the NUL bytes
+    #[default]
+    Strip,
+    /// Replace each NUL byte with `\0`
+    Escape,
+}
+
+impl NullBytesPolicy {
+    fn apply(self, value: String) -> String {
+        if !value.contains('\0') {
+            return value;
+        }
+        match self {
+            NullBytesPolicy::Strip => value.replace('\0', ""),
+            NullBytesPolicy::Escape => value.replace('\0', "\\0"),
+        }
+    }
+}
+
+/// Keys of the values in the JSON records
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonKeyStyle {
+    /// Column names as in the CSV header (e.g. `PatientID`)
+    #[default]
+    Alias,
+    /// Tags as `ggggeeee`, stable across dictionary editions (e.g. `00100020`)
+    Hex,
+}
+
+/// Subcommands other than the CSV export
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the elements of a single file (like a lightweight dcmdump)
+    Inspect(InspectArgs),
+    /// Check that fixtures in the common transfer syntaxes are extracted correctly
+    Selftest,
+}
+
 /// A tag extension for parsing
 #[derive(Debug, Clone, Copy)]
 struct TagExt(Tag);
 
-struct TagParseError(String);
+pub struct TagParseError(String);
 
 impl std::fmt::Display for TagParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -70,6 +608,9 @@ impl std::str::FromStr for TagExt {
         if let Ok(tag) = tag {
             return Ok(TagExt(tag));
         }
+        if let Some(tag) = dictionary::tag_by_alias(s) {
+            return Ok(TagExt(tag));
+        }
         // try from usual alias (e.g. "PatientName" or "SOPInstanceUID")
         let tag = StandardDataDictionary.by_name(s);
         if let Some(tag) = tag {
@@ -79,168 +620,2719 @@ impl std::str::FromStr for TagExt {
     }
 }
 
-fn dump_tags<'a>(input: &PathBuf, read_until: Tag, tags: &'a [Tag]) -> HashMap<&'a Tag, String> {
-    let open_options = dicom_object::OpenFileOptions::new();
-    let reader = open_options
-        .read_until(read_until)
-        .open_file(input)
-        .unwrap();
-    let mut map = HashMap::new();
-    for tag in tags {
-        let elm = reader.get(*tag);
-        let value = elm
-            .map(|e| e.to_str().unwrap_or_default().to_string())
-            .unwrap_or_default();
-        debug!("Tag: {tag:?} Value: {}", value);
-        map.insert(tag, value);
-    }
-    map
+/// Parse a tag the same way as the command line arguments.
+///
+/// Accepts `ggggeeee`, `gggg,eeee`, `(gggg,eeee)`, or a keyword of the standard dictionary
+/// (e.g. `PatientName`) or of the one loaded with `--dictionary`.
+pub fn parse_tag(s: &str) -> Result<Tag, TagParseError> {
+    Ok(s.parse::<TagExt>()?.0)
 }
 
-fn tag_to_alias(tag: Tag) -> String {
-    StandardDataDictionary
-        .by_tag(tag)
-        .map(|e| DataDictionaryEntry::alias(e).to_string())
-        .unwrap_or(tag.to_string())
+/// Split a comma-separated list of tags.
+///
+/// A single `gggg,eeee` tag (optionally with a `=default`) is kept as is,
+/// but tags in a list have to be written without a comma (e.g. `ggggeeee` or keyword).
+fn split_tag_list(s: &str) -> Vec<&str> {
+    let (tag, _) = split_tag_default(s);
+    if tag.parse::<Column>().is_ok() && (tag == s || tag.contains(',')) {
+        return vec![s];
+    }
+    s.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect()
 }
 
-fn print_completions<G: Generator>(generator: G, cmd: &mut clap::Command) {
-    generate(
-        generator,
-        cmd,
-        cmd.get_name().to_string(),
-        &mut std::io::stdout(),
-    );
+/// Split a `TAG=DEFAULT` tag argument into the tag and the default
+fn split_tag_default(s: &str) -> (&str, Option<&str>) {
+    match s.split_once('=') {
+        Some((tag, default)) => (tag.trim(), Some(default)),
+        None => (s, None),
+    }
 }
 
-pub fn main(args: Args) -> Result<()> {
-    if let Some(shell) = args.complete {
-        let mut cmd = Args::command();
-        print_completions(shell, &mut cmd);
-        return Ok(());
+/// Format the value of an element as a string.
+///
+/// Multiple values are split on the value multiplicity boundary
+/// and re-joined with `separator`, so that backslashes within a single value are kept as is.
+/// With `trim`, leading and trailing whitespace and null padding are stripped from each string value.
+fn format_value(elm: &InMemElement, separator: Option<&str>, trim: bool) -> String {
+    let trim_padding = |value: &str| {
+        if trim {
+            trim_padding(value).to_string()
+        } else {
+            value.to_string()
+        }
+    };
+    match elm.value().primitive() {
+        Some(PrimitiveValue::Str(value)) => return trim_padding(value),
+        Some(PrimitiveValue::Strs(values)) => {
+            return values
+                .iter()
+                .map(|value| trim_padding(value))
+                .collect::<Vec<_>>()
+                .join(separator.unwrap_or("\\"));
+        }
+        _ => {}
     }
-    let read_until = args.read_until.parse::<TagExt>()?.0;
-    info!("Read until tag: {:?}", read_until);
-    let tags: Result<Vec<_>> = args
-        .tag
-        .iter()
-        .map(|tag_str| {
-            let tag_ext: TagExt = tag_str.parse()?;
-            let tag = tag_ext.0;
-            let tag_alias = tag_to_alias(tag);
-            info!("Parsed tag: {tag_alias} {tag:?}");
-            Ok(tag)
-        })
-        .collect();
-    let mut tags = tags?;
+    match separator {
+        Some(separator) => elm
+            .to_multi_str()
+            .map(|values| values.join(separator))
+            .unwrap_or_default(),
+        None => elm.to_str().unwrap_or_default().to_string(),
+    }
+}
+
+/// Strip leading and trailing whitespace and null padding
+fn trim_padding(value: &str) -> &str {
+    value.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+}
 
-    for tag_file in args.tag_file {
-        let file = std::fs::read_to_string(tag_file)?;
-        for line in file.lines() {
-            let tag_ext: TagExt = line.parse()?;
-            let tag = tag_ext.0;
-            let tag_alias = tag_to_alias(tag);
-            info!("Parsed tag from file: {tag_alias} {tag:?}");
-            tags.push(tag);
+fn is_binary(vr: VR) -> bool {
+    matches!(
+        vr,
+        VR::OB | VR::OD | VR::OF | VR::OL | VR::OV | VR::OW | VR::UN
+    )
+}
+
+/// Base64 of the bytes of a binary element, or `<N bytes>` if longer than `max_bytes`
+fn format_binary(elm: &InMemElement, max_bytes: usize) -> String {
+    use base64::Engine;
+    match elm.to_bytes() {
+        Ok(bytes) if bytes.len() <= max_bytes => {
+            base64::engine::general_purpose::STANDARD.encode(&bytes)
         }
+        Ok(bytes) => format!("<{} bytes>", bytes.len()),
+        // encapsulated pixel data
+        Err(_) => String::new(),
     }
+}
 
-    if tags.is_empty() {
-        eprintln!("No tags specified");
-        return Ok(());
+/// A column of the output table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    /// Value of an element
+    Tag(Tag),
+    /// Value of an element in the items of a sequence (`Sequence/index/Tag` or `Sequence/*/Tag`)
+    Item {
+        sequence: Tag,
+        index: ItemIndex,
+        tag: Tag,
+    },
+    /// Value derived from the file (e.g. `@num_fragments`)
+    Derived(Derived),
+    /// Elements of repeating groups (e.g. `60xx,0022`), expanded to a column per matching tag,
+    /// or joined into a single column if `joined` (e.g. `60xx,0022!`)
+    Pattern { pattern: TagPattern, joined: bool },
+    /// Value of an element in nested sequence items (e.g. `Seq/0/Sub/1/Tag`)
+    Nested(ItemPath),
+    /// A single value of a multi-valued element (e.g. `ImageType_2` for the second value)
+    Component { tag: Tag, index: usize },
+    /// Value of an element, or of its retired (or current) equivalent if absent
+    /// (e.g. `SliceLocation+`)
+    Coalesced(Tag),
+    /// Value length of an element in bytes as in its header (e.g. `PatientID_bytes`)
+    Length(Tag),
+    /// Label of the input the file was found in (see `--label`)
+    Cohort,
+    /// Number of items of a sequence (e.g. `ReferencedImageSequence_items`)
+    ItemCount(Tag),
+}
+
+impl Column {
+    /// Dictionary independent notation which can be parsed back (e.g. `0010,0020`)
+    pub fn spec(&self) -> String {
+        let tag = |tag: &Tag| format!("{:04X},{:04X}", tag.group(), tag.element());
+        match self {
+            Column::Tag(t) => tag(t),
+            Column::Item {
+                sequence,
+                index: ItemIndex::Index(i),
+                tag: t,
+            } => format!("{}/{i}/{}", tag(sequence), tag(t)),
+            Column::Item {
+                sequence,
+                index: ItemIndex::All,
+                tag: t,
+            } => format!("{}/*/{}", tag(sequence), tag(t)),
+            Column::Derived(derived) => derived.name().to_string(),
+            Column::Pattern { .. } => self.name(),
+            Column::Nested(path) => path.spec(),
+            Column::Component { tag: t, index } => format!("{}_{}", tag(t), index + 1),
+            Column::Coalesced(t) => format!("{}+", tag(t)),
+            Column::Length(t) => format!("{}_bytes", tag(t)),
+            Column::Cohort => self.name(),
+            Column::ItemCount(t) => format!("{}_items", tag(t)),
+        }
     }
 
-    let mut filenames = Vec::new();
-    for input in args.input {
-        if input.is_dir() {
-            for entry in std::fs::read_dir(input)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_file() && path.extension().is_some_and(|ext| ext == "dcm") {
-                    filenames.push(path);
-                }
+    /// Column name used in the header
+    pub fn name(&self) -> String {
+        match self {
+            Column::Tag(tag) => tag_to_alias(*tag),
+            Column::Item {
+                sequence,
+                index,
+                tag,
+            } => {
+                let index = match index {
+                    ItemIndex::Index(i) => i.to_string(),
+                    ItemIndex::All => "*".to_string(),
+                };
+                format!("{}/{index}/{}", tag_to_alias(*sequence), tag_to_alias(*tag))
             }
-        } else if input.is_file() {
-            filenames.push(input);
-        } else {
-            bail!("Invalid input: {:?}", input);
+            Column::Derived(derived) => derived.name().to_string(),
+            Column::Pattern { pattern, joined } => {
+                format!("{pattern}{}", if *joined { "!" } else { "" })
+            }
+            Column::Nested(path) => path.name(),
+            Column::Component { tag, index } => format!("{}_{}", tag_to_alias(*tag), index + 1),
+            Column::Coalesced(tag) => format!("{}+", tag_to_alias(*tag)),
+            Column::Length(tag) => format!("{}_bytes", tag_to_alias(*tag)),
+            Column::Cohort => "Cohort".to_string(),
+            Column::ItemCount(tag) => format!("{}_items", tag_to_alias(*tag)),
         }
     }
-    if filenames.is_empty() {
-        eprintln!("No dicom files found");
-        return Ok(());
-    }
+}
 
-    info!("Found {} files to process", filenames.len());
+impl std::str::FromStr for Column {
+    type Err = TagParseError;
 
-    if let Some(jobs) = args.jobs {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(jobs)
-            .build_global()
-            .unwrap();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "Cohort" {
+            return Ok(Column::Cohort);
+        }
+        if s.starts_with('@') {
+            return Derived::from_name(s)
+                .map(Column::Derived)
+                .ok_or_else(|| TagParseError(s.to_string()));
+        }
+        if let Some(tag) = s.strip_suffix('+') {
+            return Ok(Column::Coalesced(tag.parse::<TagExt>()?.0));
+        }
+        let (pattern, joined) = match s.strip_suffix('!') {
+            Some(pattern) => (pattern, true),
+            None => (s, false),
+        };
+        if let Some(pattern) = TagPattern::parse(pattern) {
+            return Ok(Column::Pattern { pattern, joined });
+        }
+        if let Some(tag) = s.strip_suffix("_bytes") {
+            return Ok(Column::Length(tag.parse::<TagExt>()?.0));
+        }
+        if let Some(tag) = s.strip_suffix("_items") {
+            return Ok(Column::ItemCount(tag.parse::<TagExt>()?.0));
+        }
+        if let Some((tag, n)) = s.rsplit_once('_')
+            && let Ok(n @ 1..) = n.parse::<usize>()
+        {
+            return Ok(Column::Component {
+                tag: tag.parse::<TagExt>()?.0,
+                index: n - 1,
+            });
+        }
+        if s.split('/').count() > 3 {
+            return Ok(Column::Nested(ItemPath::parse(s)?));
+        }
+        if let Some((sequence, rest)) = s.split_once('/') {
+            let (index, tag) = rest
+                .split_once('/')
+                .ok_or_else(|| TagParseError(s.to_string()))?;
+            let index = match index {
+                "*" => ItemIndex::All,
+                index => ItemIndex::Index(index.parse().map_err(|_| TagParseError(s.to_string()))?),
+            };
+            return Ok(Column::Item {
+                sequence: sequence.parse::<TagExt>()?.0,
+                index,
+                tag: tag.parse::<TagExt>()?.0,
+            });
+        }
+        Ok(Column::Tag(s.parse::<TagExt>()?.0))
     }
+}
 
-    // use rayon for parallel processing
-    let maps: Vec<_> = filenames
-        .into_par_iter()
-        .map(|input| {
-            info!("Processing file: {:?}", input);
-            let map = dump_tags(&input, read_until, &tags);
-            (input, map)
-        })
-        .collect();
-    info!("Finished processing files");
+/// Items of a sequence to read a value from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ItemIndex {
+    /// A single item
+    Index(usize),
+    /// Every item, with the values joined
+    All,
+}
 
-    // write as csv
-    let mut writer = {
-        let writer: Box<dyn std::io::Write> = if let Some(output) = args.output {
-            let file = std::fs::File::create(output)?;
-            Box::new(file)
-        } else {
-            Box::new(std::io::stdout())
-        };
-        csv::Writer::from_writer(writer)
-    };
-    let mut header = vec!["FileName".to_string()];
-    header.extend(tags.iter().map(|tag| tag_to_alias(*tag)));
-    writer.write_record(&header)?;
-    for (input, map) in maps {
-        let mut row = Vec::with_capacity(header.len() + 1);
-        let file_name = input.file_name().unwrap().to_str().unwrap();
-        row.push(file_name);
-        for tag in &tags {
-            let value = map.get(tag);
-            if let Some(value) = value {
-                row.push(value.as_str());
-            } else {
-                row.push("");
+/// Values extracted from a single file, keyed by column
+pub type Row = HashMap<Column, String>;
+
+/// Options controlling how files are read and how values are formatted
+#[derive(Debug, Default, Clone)]
+struct DumpOptions {
+    /// Separator for multi-valued elements (backslash if `None`)
+    value_separator: Option<String>,
+    /// Also accept files without the 128-byte preamble
+    no_preamble: bool,
+    /// Keep the padding of string values
+    no_trim: bool,
+    null_bytes_policy: NullBytesPolicy,
+    /// Also extract every top-level element of the data set
+    all_tags: bool,
+    /// Frames to compute pixel statistics over
+    pixel_frames: PixelFrames,
+    /// Maximum number of characters of each value
+    max_value_len: Option<usize>,
+    truncate_mode: TruncateMode,
+    /// Maximum number of bytes of binary values to write as base64
+    base64_binary: Option<usize>,
+    /// Order of the components to reformat person names into
+    person_name_order: Option<Vec<NameComponent>>,
+    /// Column names overriding the aliases
+    names: HashMap<Column, String>,
+    transforms: HashMap<Column, tagfile::Transform>,
+    /// External commands the values are piped through
+    value_filters: HashMap<Column, std::sync::Arc<value_filter::ValueFilter>>,
+    /// Lookup tables applied to the values
+    lookups: HashMap<Column, std::sync::Arc<lookup::Lookup>>,
+    /// Also extract the value lengths of the elements in all-tags mode
+    show_bytes: bool,
+    /// VRs of the elements extracted in all-tags mode (all if empty)
+    tag_vrs: Vec<VR>,
+    /// Also extract the elements in sequence items in all-tags mode
+    flatten_sequences: bool,
+    max_sequence_depth: Option<usize>,
+    /// Provenance to embed in the JSON records
+    provenance: Option<json::Provenance>,
+    json_key_style: JsonKeyStyle,
+    /// Maximum depth of the sequences written with their items in DICOM JSON
+    max_json_depth: Option<usize>,
+    cohorts: cohort::Cohorts,
+    /// Maximum number of bytes read from each file
+    max_read_bytes: Option<u64>,
+    /// Tags whose values are also extracted as a component column per value
+    split_tags: Vec<Tag>,
+    /// Maximum number of component columns of each of `split_tags`
+    max_split_values: usize,
+    /// Number of files cut off by `max_read_bytes`
+    partial_reads: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl DumpOptions {
+    /// Name of `column` in the output
+    fn column_name(&self, column: &Column) -> String {
+        self.names
+            .get(column)
+            .cloned()
+            .unwrap_or_else(|| column.name())
+    }
+
+    /// Rename the columns whose names collide with those of earlier columns
+    /// by suffixing `_2`, `_3`, ...
+    fn make_names_unique(&mut self, columns: &[Column]) {
+        let mut used = HashSet::new();
+        for column in columns {
+            let name = self.column_name(column);
+            if used.insert(name.clone()) {
+                continue;
             }
+            let unique = (2..)
+                .map(|n| format!("{name}_{n}"))
+                .find(|n| !used.contains(n))
+                .unwrap();
+            debug!("Renaming a duplicate column {name} to {unique}");
+            used.insert(unique.clone());
+            self.names.insert(*column, unique);
         }
-        writer.write_record(row.iter())?;
     }
-    writer.flush()?;
-    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use dicom_core::Tag;
+/// How values longer than `--max-value-len` are truncated
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TruncateMode {
+    /// Keep the beginning of the value
+    #[default]
+    End,
+    /// Keep both the beginning and the end of the value
+    Middle,
+}
 
-    #[test]
-    fn test_tag_to_alias() {
-        let tag = Tag(0x0010, 0x0010);
-        let alias = tag_to_alias(tag);
-        assert_eq!(alias, "PatientName");
+const ELLIPSIS: char = '…';
+
+/// Truncate `value` to `max_len` characters, including the ellipsis marker
+/// (so nothing is left of a value with `max_len` of 0)
+fn truncate_value(value: String, max_len: usize, mode: TruncateMode) -> String {
+    let len = value.chars().count();
+    if len <= max_len {
+        return value;
+    }
+    if max_len == 0 {
+        return String::new();
     }
+    let keep = max_len - 1;
+    let (head, tail) = match mode {
+        TruncateMode::End => (keep, 0),
+        TruncateMode::Middle => (keep - keep / 2, keep / 2),
+    };
+    let mut truncated: String = value.chars().take(head).collect();
+    truncated.push(ELLIPSIS);
+    truncated.extend(value.chars().skip(len - tail));
+    truncated
+}
 
-    #[test]
-    fn test_tag_ext_from_str() {
-        let tag_str = "0010,0010";
-        let tag_ext: TagExt = tag_str.parse().unwrap();
-        assert_eq!(tag_ext.0, Tag(0x0010, 0x0010));
-        let tag_str = "PatientName";
-        let tag_ext: TagExt = tag_str.parse().unwrap();
-        assert_eq!(tag_ext.0, Tag(0x0010, 0x0010));
+/// Check for the `DICM` magic code following the 128-byte preamble
+fn has_dicom_magic(path: &Path) -> Result<bool> {
+    let mut buf = [0; 132];
+    let mut file = std::fs::File::open(path).with_context(|| format!("Failed to open {path:?}"))?;
+    match std::io::Read::read_exact(&mut file, &mut buf) {
+        Ok(()) => Ok(&buf[128..] == b"DICM"),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Open a DICOM file, reading only until `read_until`.
+///
+/// With `no_preamble`, reading is retried without the 128-byte preamble,
+/// and then as a file which starts directly with the file meta group
+/// (i.e. without the `DICM` magic code).
+/// Entries of zip archives (`archive.zip/entry`) are read into memory.
+fn open_dicom(
+    input: &Path,
+    read_until: Option<Tag>,
+    no_preamble: bool,
+) -> Result<DefaultDicomObject> {
+    let options = match read_until {
+        Some(tag) => OpenFileOptions::new().read_until(tag),
+        None => OpenFileOptions::new().read_all(),
+    };
+    if let Some((archive, name)) = archive::split_entry_path(input) {
+        let data = archive::read_entry(archive, &name)
+            .with_context(|| format!("Failed to read {input:?}"))?;
+        let obj = if data.get(128..132) == Some(b"DICM") {
+            options
+                .read_preamble(ReadPreamble::Always)
+                .from_reader(&data[..])
+        } else if !no_preamble {
+            bail!("{input:?} is not a DICOM file (missing DICM magic code)");
+        } else if data.starts_with(b"DICM") {
+            options
+                .read_preamble(ReadPreamble::Never)
+                .from_reader(&data[..])
+        } else {
+            options
+                .read_preamble(ReadPreamble::Never)
+                .from_reader(std::io::Read::chain(&b"DICM"[..], &data[..]))
+        };
+        return obj.with_context(|| format!("Failed to open {input:?}"));
+    }
+    if !no_preamble {
+        // files without a preamble are not recognizable by the magic code
+        if !has_dicom_magic(input)? {
+            bail!("{input:?} is not a DICOM file (missing DICM magic code)");
+        }
+        return options
+            .open_file(input)
+            .with_context(|| format!("Failed to open {input:?}"));
+    }
+    match options
+        .clone()
+        .read_preamble(ReadPreamble::Always)
+        .open_file(input)
+    {
+        Ok(obj) => return Ok(obj),
+        Err(e) => debug!("Failed to read {input:?} with preamble: {e}"),
+    }
+    match options
+        .clone()
+        .read_preamble(ReadPreamble::Never)
+        .open_file(input)
+    {
+        Ok(obj) => return Ok(obj),
+        Err(e) => debug!("Failed to read {input:?} without preamble: {e}"),
+    }
+    let file = std::io::BufReader::new(std::fs::File::open(input)?);
+    options
+        .read_preamble(ReadPreamble::Never)
+        .from_reader(std::io::Read::chain(&b"DICM"[..], file))
+        .with_context(|| format!("Failed to open {input:?}"))
+}
+
+fn dump_tags(
+    input: &Path,
+    read_until: Tag,
+    columns: &[Column],
+    options: &DumpOptions,
+) -> Result<Row> {
+    // pixel data is only read when required since it is expensive
+    let needs_pixel_data = columns
+        .iter()
+        .any(|c| matches!(c, Column::Derived(d) if d.needs_pixel_data()));
+    let read_until = (!needs_pixel_data).then_some(read_until);
+    let reader = match options.max_read_bytes {
+        Some(max_bytes) => {
+            let (obj, cut_off) = partial::open_partial(input, read_until, max_bytes)?;
+            if cut_off {
+                options
+                    .partial_reads
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+            obj
+        }
+        None => open_dicom(input, read_until, options.no_preamble)?,
+    };
+    // applied to every value taken from the elements
+    let finish = |value: String| {
+        let value = options.null_bytes_policy.apply(value);
+        match options.max_value_len {
+            Some(max_len) => truncate_value(value, max_len, options.truncate_mode),
+            None => value,
+        }
+    };
+    let format = |elm: &InMemElement| {
+        let value = match (&options.person_name_order, options.base64_binary) {
+            (_, Some(max_bytes)) if is_binary(elm.vr()) => format_binary(elm, max_bytes),
+            (Some(order), _) if elm.vr() == VR::PN => elm
+                .to_multi_str()
+                .map(|names| {
+                    names
+                        .iter()
+                        .map(|name| person_name::normalize(name, order))
+                        .collect::<Vec<_>>()
+                        .join(options.value_separator.as_deref().unwrap_or("\\"))
+                })
+                .unwrap_or_default(),
+            _ => format_value(elm, options.value_separator.as_deref(), !options.no_trim),
+        };
+        finish(value)
+    };
+    // applied to each of the values of a multi-valued element
+    let format_component = |value: &str| {
+        finish(if options.no_trim {
+            value.to_string()
+        } else {
+            trim_padding(value).to_string()
+        })
+    };
+    let ctx = DerivedContext::new(
+        input,
+        &reader,
+        options.pixel_frames,
+        options.value_separator.as_deref().unwrap_or("\\"),
+    );
+    let post_process = |column: &Column, value: String| -> Result<String> {
+        let value = match options.transforms.get(column) {
+            Some(transform) => transform.apply(value),
+            None => value,
+        };
+        let value = match options.value_filters.get(column) {
+            Some(filter) => filter.apply(&value)?,
+            None => value,
+        };
+        Ok(match options.lookups.get(column) {
+            Some(lookup) => lookup.apply(value),
+            None => value,
+        })
+    };
+    let mut map = HashMap::new();
+    for column in columns {
+        let value = match column {
+            Column::Tag(tag) => reader.get(*tag).map(format).unwrap_or_default(),
+            Column::Item {
+                sequence,
+                index,
+                tag,
+            } => {
+                let items = reader
+                    .get(*sequence)
+                    .and_then(|e| e.items())
+                    .unwrap_or_default();
+                match index {
+                    ItemIndex::Index(i) => items
+                        .get(*i)
+                        .and_then(|item| item.get(*tag))
+                        .map(format)
+                        .unwrap_or_default(),
+                    ItemIndex::All => items
+                        .iter()
+                        .filter_map(|item| item.get(*tag))
+                        .map(format)
+                        .collect::<Vec<_>>()
+                        .join(options.value_separator.as_deref().unwrap_or("\\")),
+                }
+            }
+            // computed from the other values below
+            Column::Derived(Derived::MetadataHash | Derived::SeriesIndex) => continue,
+            Column::Derived(derived) => derived.compute(&ctx).unwrap_or_else(|e| {
+                warn!("Failed to compute {} for {:?}: {e}", derived.name(), input);
+                String::new()
+            }),
+            Column::Nested(path) => path.get(&reader).map(format).unwrap_or_default(),
+            Column::Coalesced(tag) => std::iter::once(*tag)
+                .chain(retired::equivalent(*tag))
+                .filter_map(|tag| reader.get(tag))
+                .map(format)
+                .find(|value| !value.is_empty())
+                .unwrap_or_default(),
+            Column::Cohort => options.cohorts.label(input).to_string(),
+            Column::ItemCount(tag) => reader
+                .get(*tag)
+                .and_then(|elm| elm.items())
+                .map(|items| items.len().to_string())
+                .unwrap_or_default(),
+            Column::Length(tag) => reader
+                .get(*tag)
+                .and_then(|elm| elm.length().get())
+                .map(|len| len.to_string())
+                .unwrap_or_default(),
+            Column::Component { tag, index } => reader
+                .get(*tag)
+                .and_then(|elm| elm.to_multi_str().ok())
+                .and_then(|values| values.get(*index).map(|value| format_component(value)))
+                .unwrap_or_default(),
+            Column::Pattern {
+                pattern,
+                joined: true,
+            } => reader
+                .iter()
+                .filter(|elm| pattern.matches(elm.tag()))
+                .map(format)
+                .collect::<Vec<_>>()
+                .join(options.value_separator.as_deref().unwrap_or("\\")),
+            Column::Pattern {
+                pattern,
+                joined: false,
+            } => {
+                // expanded to tag columns by `expand_patterns`
+                for elm in reader.iter().filter(|elm| pattern.matches(elm.tag())) {
+                    map.insert(Column::Tag(elm.tag()), format(elm));
+                }
+                continue;
+            }
+        };
+        let value = post_process(column, value)?;
+        debug!("Column: {column:?} Value: {}", value);
+        map.insert(*column, value);
+    }
+    if options.all_tags {
+        let vrs = &options.tag_vrs;
+        for elm in reader
+            .iter()
+            .filter(|elm| vrs.is_empty() || vrs.contains(&elm.vr()))
+        {
+            map.entry(Column::Tag(elm.tag()))
+                .or_insert_with(|| format(elm));
+            if options.show_bytes
+                && let Some(len) = elm.length().get()
+            {
+                map.insert(Column::Length(elm.tag()), len.to_string());
+            }
+        }
+        if options.flatten_sequences {
+            let max_depth = options
+                .max_sequence_depth
+                .unwrap_or(flatten::MAX_SEQUENCE_DEPTH);
+            flatten::flatten_sequences(&reader, max_depth, vrs, &format, &mut map);
+        }
+    }
+    let hash_column = Column::Derived(Derived::MetadataHash);
+    if columns.contains(&hash_column) {
+        map.insert(hash_column, derived::metadata_hash(&map));
+    }
+    // the value of each item (empty if absent) for the JSON arrays,
+    // since the joined value cannot be split back if the values contain the separator
+    for column in columns {
+        let Column::Item {
+            sequence,
+            index: ItemIndex::All,
+            tag,
+        } = column
+        else {
+            continue;
+        };
+        let Some(items) = reader.get(*sequence).and_then(|e| e.items()) else {
+            continue;
+        };
+        map.entry(Column::ItemCount(*sequence))
+            .or_insert_with(|| items.len().to_string());
+        for (i, item) in items.iter().enumerate() {
+            let value = match item.get(*tag) {
+                Some(elm) => post_process(column, format(elm))?,
+                None => String::new(),
+            };
+            map.entry(Column::Item {
+                sequence: *sequence,
+                index: ItemIndex::Index(i),
+                tag: *tag,
+            })
+            .or_insert(value);
+        }
+    }
+    // split at the boundaries of the values, which may contain the separator
+    for tag in &options.split_tags {
+        let Some(values) = reader
+            .get(*tag)
+            .and_then(|elm| elm.to_multi_str().ok())
+            .filter(|values| values.iter().any(|value| !value.is_empty()))
+        else {
+            continue;
+        };
+        for (index, value) in values.iter().take(options.max_split_values).enumerate() {
+            map.insert(
+                Column::Component { tag: *tag, index },
+                format_component(value),
+            );
+        }
+    }
+    Ok(map)
+}
+
+/// Replace the expanding pattern columns with the matching tags found in `rows`
+fn expand_patterns<'a>(
+    columns: &[Column],
+    rows: impl Iterator<Item = &'a Row> + Clone,
+) -> Vec<Column> {
+    let mut expanded = Vec::with_capacity(columns.len());
+    for column in columns {
+        let Column::Pattern {
+            pattern,
+            joined: false,
+        } = column
+        else {
+            expanded.push(*column);
+            continue;
+        };
+        let mut matched: Vec<_> = rows
+            .clone()
+            .flat_map(|row| row.keys())
+            .filter_map(|c| match c {
+                Column::Tag(tag) if pattern.matches(*tag) => Some(*tag),
+                _ => None,
+            })
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter(|tag| !columns.contains(&Column::Tag(*tag)))
+            .collect();
+        matched.sort();
+        expanded.extend(matched.into_iter().map(Column::Tag));
+    }
+    expanded
+}
+
+/// Replace the columns of `split` tags with a column per value.
+///
+/// The values are extracted into the rows as components (see `DumpOptions::split_tags`),
+/// and the number of columns is the largest number of values found.
+fn split_multivalue(columns: &[Column], split: &[Tag], rows: &[(PathBuf, Row)]) -> Vec<Column> {
+    let mut reshaped = Vec::with_capacity(columns.len());
+    for column in columns {
+        let Column::Tag(tag) = column else {
+            reshaped.push(*column);
+            continue;
+        };
+        if !split.contains(tag) {
+            reshaped.push(*column);
+            continue;
+        }
+        let n_values = rows
+            .iter()
+            .map(|(_, row)| {
+                (0..)
+                    .take_while(|&index| row.contains_key(&Column::Component { tag: *tag, index }))
+                    .count()
+            })
+            .max()
+            .unwrap_or(0);
+        reshaped.extend((0..n_values).map(|index| Column::Component { tag: *tag, index }));
+    }
+    reshaped
+}
+
+/// Order of the columns found in all-tags mode
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TagOrder {
+    /// Sorted by group and element numbers
+    #[default]
+    GroupElement,
+    /// In the order of first appearance in the input files
+    FirstSeen,
+    /// Sorted by alias
+    Alphabetical,
+}
+
+/// Union of the tag columns in `rows`, excluding the already requested `columns`
+fn union_columns<'a>(
+    rows: impl Iterator<Item = &'a Row>,
+    columns: &[Column],
+    order: TagOrder,
+) -> Vec<Column> {
+    // flattened sequence items follow their top-level sequence
+    let sort_key = |column: &Column| match column {
+        Column::Nested(path) => (path.root(), Some(*path)),
+        Column::Tag(tag) => (*tag, None),
+        _ => unreachable!(),
+    };
+    let mut union = Vec::new();
+    let mut seen: HashSet<Column> = columns.iter().copied().collect();
+    for row in rows {
+        let mut found: Vec<_> = row
+            .keys()
+            .filter(|column| matches!(column, Column::Tag(_) | Column::Nested(_)))
+            .copied()
+            .collect();
+        found.sort_by_key(sort_key);
+        for column in found {
+            if seen.insert(column) {
+                union.push(column);
+            }
+        }
+    }
+    match order {
+        TagOrder::GroupElement => union.sort_by_key(sort_key),
+        TagOrder::FirstSeen => {}
+        TagOrder::Alphabetical => union.sort_by_cached_key(Column::name),
+    }
+    union
+}
+
+/// The `limit` columns present in the most rows, in their original order
+fn most_present_columns<'a>(
+    columns: &[Column],
+    rows: impl Iterator<Item = &'a Row>,
+    limit: usize,
+) -> Vec<Column> {
+    let mut counts: HashMap<Column, usize> = columns.iter().map(|c| (*c, 0)).collect();
+    for row in rows {
+        for column in row.keys() {
+            if let Some(count) = counts.get_mut(column) {
+                *count += 1;
+            }
+        }
+    }
+    let mut ranked: Vec<_> = columns.iter().enumerate().collect();
+    // the sort is stable, so ties are kept in the original order
+    ranked.sort_by_key(|(_, column)| std::cmp::Reverse(counts[*column]));
+    ranked.truncate(limit);
+    ranked.sort_by_key(|(i, _)| *i);
+    ranked.into_iter().map(|(_, column)| *column).collect()
+}
+
+/// Insert the value length column after each tag column which does not have one
+pub(crate) fn with_byte_lengths(columns: &[Column]) -> Vec<Column> {
+    let mut with_lengths = Vec::with_capacity(columns.len() * 2);
+    for column in columns {
+        if matches!(column, Column::Length(_)) {
+            continue;
+        }
+        with_lengths.push(*column);
+        if let Column::Tag(tag) = column {
+            with_lengths.push(Column::Length(*tag));
+        }
+    }
+    // explicitly requested lengths of other columns are kept
+    for column in columns {
+        if !with_lengths.contains(column) {
+            with_lengths.push(*column);
+        }
+    }
+    with_lengths
+}
+
+/// Insert an item count column after each tag column of a sequence in the dictionary
+fn with_item_counts(columns: &[Column]) -> Vec<Column> {
+    let mut with_counts = Vec::with_capacity(columns.len());
+    for column in columns {
+        with_counts.push(*column);
+        if let Column::Tag(tag) = column
+            && !columns.contains(&Column::ItemCount(*tag))
+            && is_sequence_tag(*tag)
+        {
+            with_counts.push(Column::ItemCount(*tag));
+        }
+    }
+    with_counts
+}
+
+/// Whether `tag` is a sequence according to the dictionary
+fn is_sequence_tag(tag: Tag) -> bool {
+    StandardDataDictionary
+        .by_tag(tag)
+        .is_some_and(|e| e.vr() == VirtualVr::Exact(VR::SQ))
+}
+
+/// Whether the values of `column` are numbers according to the VR in the dictionary
+pub(crate) fn is_numeric_column(column: &Column) -> bool {
+    let tag = match column {
+        Column::Tag(tag) => *tag,
+        Column::Item {
+            index: ItemIndex::Index(_),
+            tag,
+            ..
+        } => *tag,
+        Column::Nested(path) => path.tag(),
+        Column::Component { tag, .. } | Column::Coalesced(tag) => *tag,
+        Column::Length(_) | Column::ItemCount(_) => return true,
+        _ => return false,
+    };
+    StandardDataDictionary.by_tag(tag).is_some_and(|e| {
+        matches!(
+            e.vr(),
+            VirtualVr::Exact(VR::DS | VR::IS | VR::FL | VR::FD | VR::US | VR::SS | VR::UL | VR::SL)
+        )
+    })
+}
+
+/// Value of `column` in `row`, falling back to the default if absent or empty
+fn cell<'a>(row: &'a Row, column: &Column, defaults: &'a HashMap<Column, String>) -> &'a str {
+    match row.get(column).map(String::as_str) {
+        Some(value) if !value.is_empty() => value,
+        _ => defaults.get(column).map(String::as_str).unwrap_or_default(),
+    }
+}
+
+/// Extract the values of `columns` from the file at `path`
+pub fn extract(path: &Path, read_until: Tag, columns: &[Column]) -> Result<Row> {
+    Extractor::new(read_until, columns.to_vec()).extract(path)
+}
+
+/// Extraction of a fixed set of columns, resolved once and reused across files
+#[derive(Debug, Clone)]
+pub struct Extractor {
+    read_until: Tag,
+    columns: Vec<Column>,
+    options: DumpOptions,
+}
+
+impl Extractor {
+    pub fn new(read_until: Tag, columns: Vec<Column>) -> Self {
+        Extractor {
+            read_until,
+            columns,
+            options: DumpOptions::default(),
+        }
+    }
+
+    /// Resolve `read_until` and the columns from tag names (e.g. `PatientID` or `0010,0020`)
+    pub fn from_names<S: AsRef<str>>(read_until: &str, names: &[S]) -> Result<Self> {
+        let read_until = read_until.parse::<TagExt>()?.0;
+        let columns = names
+            .iter()
+            .map(|name| name.as_ref().parse())
+            .collect::<Result<_, _>>()?;
+        Ok(Extractor::new(read_until, columns))
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Extract the values of the columns from the file at `path`
+    pub fn extract(&self, path: &Path) -> Result<Row> {
+        dump_tags(path, self.read_until, &self.columns, &self.options)
+    }
+}
+
+/// Lazily extract the values of `columns` from each of `paths`.
+///
+/// Files are processed in parallel and results are yielded in the order of completion.
+/// At most a few results are buffered ahead of the consumer,
+/// and processing stops once the returned iterator is dropped.
+pub fn extract_iter<I>(
+    paths: I,
+    read_until: Tag,
+    columns: &[Column],
+) -> impl Iterator<Item = (PathBuf, Result<Row>)> + use<I>
+where
+    I: IntoIterator<Item = PathBuf>,
+    I::IntoIter: Send + 'static,
+{
+    let (sender, receiver) = std::sync::mpsc::sync_channel(rayon::current_num_threads() * 2);
+    let extractor = Extractor::new(read_until, columns.to_vec());
+    let paths = paths.into_iter();
+    std::thread::spawn(move || {
+        paths
+            .par_bridge()
+            .try_for_each_with(sender, |sender, path| {
+                let row = catch_panic(|| extractor.extract(&path));
+                sender.send((path, row))
+            })
+    });
+    receiver.into_iter()
+}
+
+/// Number of files failed so far, to stop once it exceeds `--max-errors`
+struct ErrorLimit {
+    max_errors: Option<usize>,
+    n_errors: std::sync::atomic::AtomicUsize,
+}
+
+impl ErrorLimit {
+    fn new(max_errors: Option<usize>) -> Self {
+        ErrorLimit {
+            max_errors,
+            n_errors: Default::default(),
+        }
+    }
+
+    /// Count a failure of `input`, failing if there are more than allowed
+    fn check<T>(&self, input: &Path, result: &Result<T>) -> Result<()> {
+        let (Err(e), Some(max_errors)) = (result, self.max_errors) else {
+            return Ok(());
+        };
+        let n_errors = self
+            .n_errors
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+            + 1;
+        if n_errors > max_errors {
+            bail!(
+                "Stopped since more than {max_errors} files failed to be processed, \
+                 the last being {input:?}: {e:#}"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Run `f`, converting a panic into an error with the panic message
+fn catch_panic<T>(f: impl FnOnce() -> Result<T>) -> Result<T> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        Err(anyhow!("Panicked: {message}"))
+    })
+}
+
+fn tag_to_alias(tag: Tag) -> String {
+    if let Some(alias) = dictionary::alias_by_tag(tag) {
+        return alias;
+    }
+    match StandardDataDictionary.by_tag(tag) {
+        // entries of repeating groups share the alias, so the tag is appended to tell them apart
+        // except for the first group (e.g. 6000), which keeps the plain alias
+        Some(e)
+            if matches!(
+                e.tag_range(),
+                TagRange::Group100(base) | TagRange::Element100(base) if base != tag
+            ) =>
+        {
+            format!("{}{tag}", DataDictionaryEntry::alias(e))
+        }
+        Some(e) => DataDictionaryEntry::alias(e).to_string(),
+        None => tag.to_string(),
+    }
+}
+
+/// Open a file, or an entry of a zip archive given as `archive.zip/entry`
+pub(crate) fn open_input(path: &Path) -> Result<Box<dyn std::io::Read>> {
+    match archive::split_entry_path(path) {
+        Some((archive, name)) => Ok(Box::new(std::io::Cursor::new(archive::read_entry(
+            archive, &name,
+        )?))),
+        None => Ok(Box::new(std::fs::File::open(path)?)),
+    }
+}
+
+/// Collect `.dcm` files in `dir`, descending at most `max_depth` levels.
+///
+/// Symlinked subdirectories are skipped unless `follow_symlinks` is set.
+fn collect_dicom_files(
+    dir: &Path,
+    max_depth: usize,
+    follow_symlinks: bool,
+    filenames: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let mut visited = HashSet::new();
+    walk_dir(dir, max_depth, follow_symlinks, &mut visited, filenames)
+}
+
+fn walk_dir(
+    dir: &Path,
+    max_depth: usize,
+    follow_symlinks: bool,
+    visited: &mut HashSet<PathBuf>,
+    filenames: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if max_depth == 0 {
+        return Ok(());
+    }
+    // a symlink back to a parent would be searched again and again
+    if follow_symlinks && !visited.insert(std::fs::canonicalize(dir)?) {
+        debug!("Skipping {:?} already searched", dir);
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "dcm") {
+            filenames.push(path);
+        } else if path.is_dir() && max_depth > 1 {
+            if entry.file_type()?.is_symlink() && !follow_symlinks {
+                debug!("Skipping symlinked directory {:?}", path);
+                continue;
+            }
+            walk_dir(&path, max_depth - 1, follow_symlinks, visited, filenames)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read newline-separated paths, skipping blank lines and `#` comments
+fn read_input_list(path: &Path) -> Result<Vec<PathBuf>> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Count the distinct values of `column`, most frequent first.
+///
+/// With `case_fold`, values differing only in case are counted together
+/// under the first spelling found.
+fn value_counts<'a>(
+    rows: impl Iterator<Item = &'a Row>,
+    column: &Column,
+    defaults: &'a HashMap<Column, String>,
+    case_fold: bool,
+) -> Vec<(&'a str, usize)> {
+    let mut counts: HashMap<Cow<str>, (&str, usize)> = HashMap::new();
+    for row in rows {
+        let value = cell(row, column, defaults);
+        let key = if case_fold {
+            Cow::Owned(value.to_lowercase())
+        } else {
+            Cow::Borrowed(value)
+        };
+        counts.entry(key).or_insert((value, 0)).1 += 1;
+    }
+    let mut counts: Vec<_> = counts.into_values().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+/// Minimum and maximum number of characters of the non-empty values of `column`,
+/// and the number of non-empty values
+fn length_stats<'a>(
+    rows: impl Iterator<Item = &'a Row>,
+    column: &Column,
+    defaults: &HashMap<Column, String>,
+) -> (Option<usize>, Option<usize>, usize) {
+    let lengths: Vec<_> = rows
+        .map(|row| cell(row, column, defaults).chars().count())
+        .filter(|len| *len > 0)
+        .collect();
+    let min = lengths.iter().min().copied();
+    let max = lengths.iter().max().copied();
+    (min, max, lengths.len())
+}
+
+/// Parse a VR (e.g. `PN`) given on the command line
+fn parse_vr(s: &str) -> Result<VR, String> {
+    s.trim()
+        .to_ascii_uppercase()
+        .parse()
+        .map_err(|_| format!("Unknown VR: {s}"))
+}
+
+/// Parse a size in bytes with an optional binary suffix (e.g. `512K` or `2G`)
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (number, unit) = match s.trim().to_ascii_uppercase() {
+        s if s.ends_with('K') => (s[..s.len() - 1].to_string(), 1 << 10),
+        s if s.ends_with('M') => (s[..s.len() - 1].to_string(), 1 << 20),
+        s if s.ends_with('G') => (s[..s.len() - 1].to_string(), 1 << 30),
+        s => (s, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| format!("Invalid size: {s}"))
+}
+
+/// Parse a fraction in (0, 1]
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(p) if p > 0.0 && p <= 1.0 => Ok(p),
+        _ => Err(format!(
+            "Expected a fraction greater than 0 and at most 1: {s}"
+        )),
+    }
+}
+
+/// Randomly select `fraction` of the files (rounded) with a seeded generator,
+/// keeping their order.
+///
+/// The files are drawn in the order of their paths so that a seed selects the same
+/// files regardless of the order the directories are listed in.
+fn sample_files(filenames: Vec<PathBuf>, fraction: f64, seed: u64) -> Vec<PathBuf> {
+    let mut order: Vec<usize> = (0..filenames.len()).collect();
+    order.sort_by(|&a, &b| filenames[a].cmp(&filenames[b]));
+    fastrand::Rng::with_seed(seed).shuffle(&mut order);
+    let n = (filenames.len() as f64 * fraction).round() as usize;
+    let selected: HashSet<usize> = order[..n].iter().copied().collect();
+    filenames
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| selected.contains(i))
+        .map(|(_, input)| input)
+        .collect()
+}
+
+/// Value of the FileName column: the file name, or the entry name within the archive
+/// (e.g. `series1/IM0001`) so that entries in different directories are told apart
+fn file_name_column(input: &Path) -> Cow<'_, str> {
+    match archive::split_entry_path(input) {
+        Some((_, name)) => Cow::Owned(name),
+        None => input.file_name().unwrap().to_string_lossy(),
+    }
+}
+
+/// Read the FileName column of a previous output
+fn read_processed_files(path: &Path) -> Result<HashSet<String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_path(path)
+        .with_context(|| format!("Failed to open {path:?}"))?;
+    let index = reader
+        .headers()?
+        .iter()
+        .position(|h| h.trim_start_matches('\u{feff}') == "FileName")
+        .with_context(|| format!("No FileName column in {path:?}"))?;
+    let mut processed = HashSet::new();
+    for record in reader.records() {
+        if let Some(file_name) = record?.get(index) {
+            processed.insert(file_name.to_string());
+        }
+    }
+    Ok(processed)
+}
+
+/// Whether rows are appended to an existing, non-empty output file
+fn is_appending(args: &Args) -> bool {
+    args.append
+        && args
+            .output
+            .as_ref()
+            .and_then(|output| std::fs::metadata(output).ok())
+            .is_some_and(|metadata| metadata.len() > 0)
+}
+
+/// Open the output file (or stdout) and write the leading comment if requested.
+///
+/// Nothing is written before the rows when appending to an existing file.
+fn open_output(args: &Args) -> Result<Box<dyn std::io::Write>> {
+    open_output_to(args, args.output.as_deref())
+}
+
+/// Open `output` (or stdout) like the output file
+fn open_output_to(args: &Args, output: Option<&Path>) -> Result<Box<dyn std::io::Write>> {
+    let encode = |writer: Box<dyn std::io::Write>| -> Box<dyn std::io::Write> {
+        match args.output_encoding {
+            OutputEncoding::Utf8 => writer,
+            OutputEncoding::Latin1 => Box::new(latin1::Latin1Writer::new(writer)),
+        }
+    };
+    if is_appending(args) {
+        let output = args.output.as_ref().unwrap();
+        let file = std::fs::OpenOptions::new().append(true).open(output)?;
+        return Ok(encode(Box::new(file)));
+    }
+    if args.utf8_bom && args.output_encoding != OutputEncoding::Utf8 {
+        bail!("--utf8-bom cannot be used with --output-encoding other than utf8");
+    }
+    let writer: Box<dyn std::io::Write> = if let Some(output) = output {
+        let mut file = std::fs::File::create(output)?;
+        if args.utf8_bom {
+            file.write_all(UTF8_BOM)?;
+        }
+        Box::new(file)
+    } else {
+        if args.utf8_bom {
+            warn!("--utf8-bom is ignored when writing to stdout");
+        }
+        Box::new(std::io::stdout())
+    };
+    let mut writer = encode(writer);
+    if args.csv_comment {
+        write_provenance_comment(&mut writer)?;
+    }
+    Ok(writer)
+}
+
+/// Write provenance information as `#` prefixed comment lines
+fn write_provenance_comment(writer: &mut dyn std::io::Write) -> std::io::Result<()> {
+    let command = std::env::args().collect::<Vec<_>>().join(" ");
+    writeln!(writer, "# szkdcm {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(writer, "# command: {command}")?;
+    writeln!(writer, "# timestamp: {}", chrono::Local::now().to_rfc3339())?;
+    Ok(())
+}
+
+fn print_completions<G: Generator>(generator: G, cmd: &mut clap::Command) {
+    generate(
+        generator,
+        cmd,
+        cmd.get_name().to_string(),
+        &mut std::io::stdout(),
+    );
+}
+
+pub fn main(args: Args) -> Result<()> {
+    let mut profile = profile::Profile::new(args.profile);
+    let result = run(args, &mut profile);
+    profile.report();
+    result
+}
+
+fn run(args: Args, profile: &mut profile::Profile) -> Result<()> {
+    match &args.command {
+        Some(Command::Inspect(inspect_args)) => return inspect::main(inspect_args),
+        Some(Command::Selftest) => return selftest::main(),
+        None => {}
+    }
+    if let Some(shell) = args.complete {
+        let mut cmd = Args::command();
+        print_completions(shell, &mut cmd);
+        return Ok(());
+    }
+    if let Some(dictionary) = &args.dictionary {
+        dictionary::load(dictionary)?;
+    }
+    let read_until = args.read_until.parse::<TagExt>()?.0;
+    info!("Read until tag: {:?}", read_until);
+    let mut tags = Vec::new();
+    let mut inline_defaults = Vec::new();
+    for tag_str in args.tag.iter().flat_map(|tag_str| split_tag_list(tag_str)) {
+        let (tag_str, default) = split_tag_default(tag_str);
+        let column: Column = tag_str.parse()?;
+        info!("Parsed tag: {} {column:?}", column.name());
+        if let Some(default) = default {
+            inline_defaults.push((column, default.to_string()));
+        }
+        tags.push(column);
+    }
+
+    for preset in &args.preset {
+        for tag in preset.tags() {
+            let column = Column::Tag(*tag);
+            if !tags.contains(&column) {
+                info!("Tag from preset {preset:?}: {} {tag:?}", tag_to_alias(*tag));
+                tags.push(column);
+            }
+        }
+    }
+
+    let mut names = HashMap::new();
+    let mut transforms = HashMap::new();
+    let tag_files = args
+        .tag_file
+        .iter()
+        .map(|tag_file| tagfile::read_tag_file(tag_file, args.tag_file_format))
+        .collect::<Result<Vec<_>>>()?;
+    for spec in tagfile::merge_tag_specs(tag_files) {
+        info!(
+            "Parsed tag from file: {} {:?}",
+            spec.column.name(),
+            spec.column
+        );
+        if let Some(name) = spec.name {
+            names.insert(spec.column, name);
+        }
+        if let Some(transform) = spec.transform {
+            transforms.insert(spec.column, transform);
+        }
+        tags.push(spec.column);
+    }
+
+    for code in &args.code {
+        let sequence = code.parse::<TagExt>()?.0;
+        tags.push(Column::Item {
+            sequence,
+            index: ItemIndex::Index(0),
+            tag: tags::CODE_MEANING,
+        });
+        if args.code_value {
+            tags.push(Column::Item {
+                sequence,
+                index: ItemIndex::Index(0),
+                tag: tags::CODE_VALUE,
+            });
+        }
+    }
+
+    if args.show_encoding {
+        tags.push(Column::Derived(Derived::Encoding));
+    }
+
+    if args.show_color {
+        tags.push(Column::Tag(tags::PHOTOMETRIC_INTERPRETATION));
+        tags.push(Column::Derived(Derived::HasIccProfile));
+    }
+
+    if args.compute_series_index {
+        tags.push(Column::Tag(tags::INSTANCE_NUMBER));
+        tags.push(Column::Derived(Derived::SeriesIndex));
+    }
+
+    if args.metadata_hash {
+        tags.push(Column::Derived(Derived::MetadataHash));
+    }
+
+    if args.pixel_stats {
+        for derived in [Derived::PixelMin, Derived::PixelMax, Derived::PixelMean] {
+            tags.push(Column::Derived(derived));
+        }
+    }
+
+    if args.hierarchy {
+        let missing: Vec<_> = presets::HIERARCHY
+            .iter()
+            .map(|tag| Column::Tag(*tag))
+            .filter(|column| !tags.contains(column))
+            .collect();
+        tags.splice(0..0, missing);
+    }
+
+    let split_tags = args
+        .split_multivalue
+        .iter()
+        .map(|tag| Ok(tag.parse::<TagExt>()?.0))
+        .collect::<Result<Vec<_>>>()?;
+    for tag in &split_tags {
+        if !tags.contains(&Column::Tag(*tag)) {
+            tags.push(Column::Tag(*tag));
+        }
+    }
+
+    let aggregate_by: Option<Column> = args.aggregate_by.as_deref().map(str::parse).transpose()?;
+    let split_by: Option<Column> = args.split_by.as_deref().map(str::parse).transpose()?;
+    let aggregate_rules = args
+        .aggregate
+        .iter()
+        .map(|s| aggregate::parse_rule_arg(s))
+        .collect::<Result<Vec<_>>>()?;
+    for (column, _) in &aggregate_rules {
+        if !tags.contains(column) {
+            tags.push(*column);
+        }
+    }
+
+    let cohorts = cohort::Cohorts::new(
+        &args.label,
+        if args.label_inputs { &args.input } else { &[] },
+    )?;
+    if !cohorts.is_empty() && !tags.contains(&Column::Cohort) {
+        tags.insert(0, Column::Cohort);
+    }
+
+    // a column requested more than once is written once
+    let mut seen = HashSet::new();
+    tags.retain(|column| seen.insert(*column));
+    if args.show_bytes {
+        tags = with_byte_lengths(&tags);
+    }
+    if args.show_sequence_count {
+        tags = with_item_counts(&tags);
+    } else {
+        // a sequence has no value of its own
+        for column in &tags {
+            if let Column::Tag(tag) = column
+                && is_sequence_tag(*tag)
+            {
+                let name = column.name();
+                warn!(
+                    "{name} is a sequence and its column will be empty; \
+                     use a path such as {name}/0/Tag or {name}/*/Tag for the elements in its items"
+                );
+            }
+        }
+    }
+
+    let conformance = args
+        .conformance
+        .as_deref()
+        .map(conformance::ConformanceProfile::read)
+        .transpose()?;
+
+    let summary: Option<Column> = args.summary.as_deref().map(str::parse).transpose()?;
+    let summary_month = args
+        .summary_by_month
+        .then_some(Column::Tag(tags::STUDY_DATE));
+
+    if tags.is_empty() && !args.all_tags && conformance.is_none() && summary.is_none() {
+        eprintln!("No tags specified");
+        return Ok(());
+    }
+
+    if let Some(write_tag_file) = &args.write_tag_file {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(write_tag_file)?);
+        for column in &tags {
+            writeln!(file, "{} # {}", column.spec(), column.name())?;
+        }
+        file.flush()?;
+        return Ok(());
+    }
+
+    // --default takes precedence over the defaults given with --tag
+    let mut defaults: HashMap<_, _> = inline_defaults.into_iter().collect();
+    for default in &args.default {
+        let (tag_str, value) = default
+            .split_once('=')
+            .with_context(|| format!("Invalid default (expected TAG=VALUE): {default}"))?;
+        let column: Column = tag_str.parse()?;
+        if !tags.contains(&column) {
+            warn!(
+                "Default for {} is given but the tag is not extracted",
+                column.name()
+            );
+        }
+        defaults.insert(column, value.to_string());
+    }
+
+    let mut value_filters = HashMap::new();
+    for value_filter in &args.value_filter {
+        let (column, filter) = value_filter::parse_value_filter_arg(value_filter)?;
+        if !tags.contains(&column) {
+            warn!(
+                "Value filter for {} is given but the tag is not extracted",
+                column.name()
+            );
+        }
+        value_filters.insert(column, std::sync::Arc::new(filter));
+    }
+    let mut lookups = HashMap::new();
+    for map in &args.map {
+        let (column, lookup) = lookup::parse_map_arg(map)?;
+        if !tags.contains(&column) {
+            warn!(
+                "Map for {} is given but the tag is not extracted",
+                column.name()
+            );
+        }
+        lookups.insert(column, std::sync::Arc::new(lookup));
+    }
+
+    let date_filter = match &args.date_tag {
+        Some(date_tag) => Some(filter::DateFilter {
+            column: date_tag.parse()?,
+            from: args.date_from,
+            to: args.date_to,
+            include_undated: args.include_undated,
+        }),
+        None => None,
+    };
+    // the date is extracted for filtering even if it is not an output column
+    let mut extract_columns = tags.clone();
+    for column in conformance
+        .iter()
+        .flat_map(|profile| profile.columns())
+        .chain(aggregate_by)
+        .chain(split_by)
+        .chain(summary)
+        .chain(summary_month)
+    {
+        if !extract_columns.contains(&column) {
+            extract_columns.push(column);
+        }
+    }
+    if let Some(date_filter) = &date_filter
+        && !extract_columns.contains(&date_filter.column)
+    {
+        extract_columns.push(date_filter.column);
+    }
+    // the series and the order of the files in them
+    let series_index = tags.contains(&Column::Derived(Derived::SeriesIndex));
+    if series_index {
+        for tag in [tags::SERIES_INSTANCE_UID, tags::INSTANCE_NUMBER] {
+            if !extract_columns.contains(&Column::Tag(tag)) {
+                extract_columns.push(Column::Tag(tag));
+            }
+        }
+    }
+
+    if args.embed_provenance
+        && !args.ndjson
+        && args.json_output.is_none()
+        && args.ndjson_output.is_none()
+    {
+        warn!("--embed-provenance only applies to --json-output, --ndjson-output, and --ndjson");
+    }
+    if args.max_json_depth.is_some() && args.format != OutputFormat::DicomJson {
+        warn!("--max-json-depth only applies to --format dicom-json");
+    }
+    let mut options = DumpOptions {
+        value_separator: args.value_separator.clone(),
+        no_preamble: args.no_preamble,
+        max_read_bytes: args.max_read_bytes,
+        no_trim: args.no_trim,
+        null_bytes_policy: args.output_null_bytes_policy,
+        all_tags: args.all_tags,
+        pixel_frames: args.pixel_stats_frames,
+        max_value_len: args.max_value_len,
+        truncate_mode: args.truncate_mode,
+        base64_binary: args.base64_binary,
+        person_name_order: args.normalize_person_names.then(|| {
+            if args.name_order.is_empty() {
+                person_name::DEFAULT_ORDER.to_vec()
+            } else {
+                args.name_order.clone()
+            }
+        }),
+        names,
+        transforms,
+        value_filters,
+        lookups,
+        show_bytes: args.show_bytes,
+        tag_vrs: args.tag_vr.clone(),
+        flatten_sequences: args.flatten_sequences,
+        max_sequence_depth: args.max_sequence_depth,
+        provenance: args.embed_provenance.then(json::Provenance::new),
+        json_key_style: args.json_key_style,
+        max_json_depth: args.max_json_depth,
+        cohorts,
+        split_tags: split_tags.clone(),
+        max_split_values: args.max_split_values.unwrap_or(usize::MAX),
+        partial_reads: Default::default(),
+    };
+    options.make_names_unique(&tags);
+
+    if args.ndjson {
+        profile.start("extraction and writing");
+        let extractor = Extractor {
+            read_until,
+            columns: tags,
+            options,
+        };
+        return json::stream_ndjson(
+            std::io::stdin().lock(),
+            std::io::stdout().lock(),
+            &extractor,
+            &defaults,
+        );
+    }
+
+    profile.start("enumeration");
+    let mut inputs = args.input.clone();
+    for input_list in &args.input_list {
+        inputs.extend(read_input_list(input_list)?);
+    }
+
+    let max_depth = if args.recursive {
+        args.max_depth.unwrap_or(usize::MAX)
+    } else {
+        1
+    };
+    let watched_dirs: Vec<_> = inputs
+        .iter()
+        .filter(|input| input.is_dir())
+        .cloned()
+        .collect();
+    let mut filenames = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let n_found = filenames.len();
+            collect_dicom_files(&input, max_depth, args.follow_symlinks, &mut filenames)?;
+            if filenames.len() == n_found {
+                warn!("No dicom files found in {:?}", input);
+            }
+        } else if archive::is_archive(&input) {
+            let entries = archive::list_entries(&input)?;
+            if entries.is_empty() {
+                warn!("No dicom files found in {:?}", input);
+            }
+            filenames.extend(entries);
+        } else if input.is_file() && dicomdir::is_dicomdir(&input) {
+            let files = dicomdir::referenced_files(&input)?;
+            if files.is_empty() {
+                warn!("No files referenced in {:?}", input);
+            }
+            filenames.extend(files);
+        } else if input.is_file() {
+            filenames.push(input);
+        } else {
+            bail!("Invalid input: {:?}", input);
+        }
+    }
+    // the watched directories may be empty at first
+    if filenames.is_empty() && !args.watch {
+        eprintln!("No dicom files found");
+        return Ok(());
+    }
+    if args.dedupe_inputs {
+        let n_found = filenames.len();
+        let mut seen = HashSet::new();
+        filenames
+            .retain(|input| seen.insert(std::fs::canonicalize(input).unwrap_or(input.clone())));
+        let n_removed = n_found - filenames.len();
+        if n_removed > 0 {
+            eprintln!("Removed {n_removed} duplicate inputs");
+        }
+    }
+    // kept out of the rescans with --watch
+    let mut resumed = Vec::new();
+    if let Some(resume) = &args.resume {
+        let processed = read_processed_files(resume)?;
+        // the FileName column does not tell apart files of the same name in different directories
+        let mut seen = HashMap::new();
+        for input in &filenames {
+            let name = file_name_column(input);
+            if !processed.contains(name.as_ref()) {
+                continue;
+            }
+            if let Some(other) = seen.insert(name, input) {
+                bail!(
+                    "Cannot resume from {resume:?}: {other:?} and {input:?} are both recorded as {:?}",
+                    file_name_column(input)
+                );
+            }
+        }
+        (resumed, filenames) = filenames
+            .into_iter()
+            .partition(|input| processed.contains(file_name_column(input).as_ref()));
+        info!("Skipping {} already processed files", resumed.len());
+        if filenames.is_empty() && !args.watch {
+            eprintln!("No new files to process");
+            return Ok(());
+        }
+    }
+    let size_range = args.min_file_size.unwrap_or(0)..=args.max_file_size.unwrap_or(u64::MAX);
+    let in_size_range = |input: &PathBuf| match std::fs::metadata(input) {
+        Ok(metadata) => size_range.contains(&metadata.len()),
+        // left to be reported as a per-file error
+        Err(_) => true,
+    };
+    if args.min_file_size.is_some() || args.max_file_size.is_some() {
+        let n_found = filenames.len();
+        filenames.retain(in_size_range);
+        let n_skipped = n_found - filenames.len();
+        if n_skipped > 0 {
+            eprintln!("Skipped {n_skipped} files outside the size range");
+        }
+        if filenames.is_empty() && !args.watch {
+            return Ok(());
+        }
+    }
+    // the files which fail to be opened are left to be reported as per-file errors
+    let has_magic = |input: &PathBuf| has_dicom_magic(input).unwrap_or(true);
+    if args.require_dicm {
+        let n_found = filenames.len();
+        filenames.retain(has_magic);
+        let n_skipped = n_found - filenames.len();
+        if n_skipped > 0 {
+            eprintln!("Skipped {n_skipped} files without the DICM magic code");
+        }
+        if filenames.is_empty() && !args.watch {
+            return Ok(());
+        }
+    }
+
+    if let Some(fraction) = args.sample_random {
+        let seed = args.seed.unwrap_or_else(|| fastrand::u64(..));
+        let n_found = filenames.len();
+        filenames = sample_files(filenames, fraction, seed);
+        eprintln!(
+            "Sampled {} of {n_found} files with --seed {seed}",
+            filenames.len()
+        );
+        if filenames.is_empty() {
+            return Ok(());
+        }
+    }
+
+    info!("Found {} files to process", filenames.len());
+
+    // the global pool can only be configured once per process
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or_default())
+        .thread_name(|i| format!("szkdcm-worker-{i}"))
+        .build_global()
+    {
+        debug!("Failed to configure the thread pool: {e}");
+    }
+
+    let extractor = Extractor {
+        read_until,
+        columns: extract_columns,
+        options: options.clone(),
+    };
+    let error_limit = ErrorLimit::new(args.max_errors);
+    // catch_unwind costs next to nothing compared to reading a file
+    let abort_on_panic = args.abort_on_panic;
+    let extract = |input: &Path| {
+        if abort_on_panic {
+            extractor.extract(input)
+        } else {
+            catch_panic(|| extractor.extract(input))
+        }
+    };
+
+    if args.watch {
+        if tags
+            .iter()
+            .any(|c| matches!(c, Column::Pattern { joined: false, .. }))
+        {
+            bail!("Expanding tag patterns require all rows and cannot be used with --watch");
+        }
+        if args.format != OutputFormat::Csv {
+            bail!("--watch only supports the CSV output");
+        }
+        if args.sort_columns {
+            tags.sort_by_cached_key(|tag| options.column_name(tag));
+        }
+        let interval = std::time::Duration::from_secs_f64(args.watch_interval.unwrap_or(2.0));
+        profile.start("extraction and writing");
+        let mut writer = open_csv_writer(&args, &tags, &options)?;
+        let mut watcher = watch::Watcher::new(filenames.iter().cloned().chain(resumed));
+        let mut new_files = filenames;
+        loop {
+            let results: Vec<_> = new_files
+                .into_par_iter()
+                .map(|input| {
+                    let map = extract(&input);
+                    (input, map)
+                })
+                .collect();
+            for (input, map) in results {
+                error_limit.check(&input, &map)?;
+                match map {
+                    Ok(map)
+                        if date_filter
+                            .as_ref()
+                            .is_none_or(|f| f.matches(&map, &defaults)) =>
+                    {
+                        write_csv_row(&mut writer, &input, &map, &tags, &defaults)?;
+                    }
+                    Ok(_) => debug!("Excluded by date: {:?}", input),
+                    Err(e) => eprintln!("Skipping {:?}: {e:#}", input),
+                }
+            }
+            // make the rows visible to the readers of the output
+            writer.flush()?;
+
+            std::thread::sleep(interval);
+            let mut found = Vec::new();
+            for dir in &watched_dirs {
+                collect_dicom_files(dir, max_depth, args.follow_symlinks, &mut found)?;
+            }
+            found.retain(in_size_range);
+            new_files = watcher.poll(found);
+            if args.require_dicm {
+                new_files.retain(has_magic);
+            }
+            if !new_files.is_empty() {
+                info!("Found {} new files", new_files.len());
+            }
+        }
+    }
+
+    if let Some(max_buffered) = args.max_buffered {
+        if tags
+            .iter()
+            .any(|c| matches!(c, Column::Pattern { joined: false, .. }))
+        {
+            bail!("Expanding tag patterns require all rows and cannot be used with --max-buffered");
+        }
+        if args.format != OutputFormat::Csv {
+            bail!("--max-buffered only supports the CSV output");
+        }
+        profile.start("extraction and writing");
+        let mut writer = open_csv_writer(&args, &tags, &options)?;
+        let (sender, receiver) = std::sync::mpsc::sync_channel(max_buffered);
+        return std::thread::scope(|scope| {
+            scope.spawn(|| {
+                filenames
+                    .into_par_iter()
+                    .try_for_each_with(sender, |sender, input| {
+                        let map = extract(&input);
+                        sender.send((input, map))
+                    })
+            });
+            // rows are written in the order of completion
+            for (input, map) in receiver {
+                error_limit.check(&input, &map)?;
+                match map {
+                    Ok(map)
+                        if date_filter
+                            .as_ref()
+                            .is_none_or(|f| f.matches(&map, &defaults)) =>
+                    {
+                        write_csv_row(&mut writer, &input, &map, &tags, &defaults)?;
+                    }
+                    Ok(_) => debug!("Excluded by date: {:?}", input),
+                    Err(e) => eprintln!("Skipping {:?}: {e:#}", input),
+                }
+            }
+            writer.flush()?;
+            report_partial_reads(&options);
+            Ok(())
+        });
+    }
+
+    profile.start("extraction");
+    // use rayon for parallel processing
+    // stops processing the other files once the error limit is exceeded
+    let results: Vec<_> = filenames
+        .into_par_iter()
+        .map(|input| {
+            info!("Processing file: {:?}", input);
+            let map = extract(&input);
+            error_limit.check(&input, &map)?;
+            Ok((input, map))
+        })
+        .collect::<Result<_>>()?;
+    info!("Finished processing files");
+    report_partial_reads(&options);
+    profile.start("writing");
+
+    if args.list_errors_only {
+        let mut writer = csv_writer(&args, open_output(&args)?);
+        writer.write_record(["FileName", "Error"])?;
+        for (input, result) in &results {
+            if let Err(e) = result {
+                let file_name = file_name_column(input);
+                writer.write_record([file_name.as_ref(), &format!("{e:#}")])?;
+            }
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+    let mut maps: Vec<_> = results
+        .into_iter()
+        .filter_map(|(input, map)| match map {
+            Ok(map) => Some((input, map)),
+            Err(e) => {
+                eprintln!("Skipping {:?}: {e:#}", input);
+                None
+            }
+        })
+        .filter(|(input, map)| {
+            let keep = date_filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(map, &defaults));
+            if !keep {
+                debug!("Excluded by date: {:?}", input);
+            }
+            keep
+        })
+        .collect();
+    if series_index {
+        derived::assign_series_indices(&mut maps);
+    }
+
+    if let Some(profile) = &conformance {
+        let mut writer = csv_writer(&args, open_output(&args)?);
+        writer.write_record(["FileName", "SOPClassUID", "MissingTag"])?;
+        let mut n_violating = 0;
+        for (input, map) in &maps {
+            let Some((uid, missing)) = profile.violations(map) else {
+                debug!("No conformance profile for the SOP class of {:?}", input);
+                continue;
+            };
+            if !missing.is_empty() {
+                n_violating += 1;
+            }
+            let file_name = file_name_column(input);
+            for column in missing {
+                writer.write_record([file_name.as_ref(), uid, &column.name()])?;
+            }
+        }
+        writer.flush()?;
+        eprintln!(
+            "{n_violating} of {} files violate the conformance profile",
+            maps.len()
+        );
+        return Ok(());
+    }
+
+    tags = expand_patterns(&tags, maps.iter().map(|(_, map)| map));
+    if args.all_tags {
+        let rows = maps.iter().map(|(_, map)| map);
+        let mut union = union_columns(rows.clone(), &tags, args.tag_order);
+        if let Some(limit) = args.columns_limit
+            && union.len() > limit
+        {
+            eprintln!(
+                "Dropped {} of {} columns found in all-tags mode (--columns-limit {limit})",
+                union.len() - limit,
+                union.len()
+            );
+            union = most_present_columns(&union, rows, limit);
+        }
+        tags.extend(union);
+        if args.show_bytes {
+            tags = with_byte_lengths(&tags);
+        }
+    }
+    if !split_tags.is_empty() {
+        tags = split_multivalue(&tags, &split_tags, &maps);
+    }
+    if args.sort_columns {
+        tags.sort_by_cached_key(|tag| options.column_name(tag));
+    }
+    if args.skip_empty_rows {
+        let n_rows = maps.len();
+        // the Cohort label is not a value of the file
+        maps.retain(|(_, map)| {
+            tags.iter()
+                .filter(|tag| **tag != Column::Cohort)
+                .any(|tag| !cell(map, tag, &defaults).is_empty())
+        });
+        let n_skipped = n_rows - maps.len();
+        if n_skipped > 0 {
+            eprintln!("Skipped {n_skipped} files without any of the values");
+        }
+    }
+    if args.exclude_empty_columns {
+        let n_columns = tags.len();
+        tags.retain(|tag| {
+            maps.iter()
+                .any(|(_, map)| !cell(map, tag, &defaults).is_empty())
+        });
+        info!("Excluded {} empty columns", n_columns - tags.len());
+    }
+    // columns added from the rows may share names with the requested ones
+    options.make_names_unique(&tags);
+
+    if let Some(key) = &aggregate_by {
+        // tags without a rule (e.g. series-constant ones) are shown once with `first`
+        let mut columns = Vec::new();
+        for tag in tags.iter().filter(|tag| *tag != key) {
+            let rules: Vec<_> = aggregate_rules
+                .iter()
+                .filter(|(column, _)| column == tag)
+                .map(|(_, rule)| *rule)
+                .collect();
+            if rules.is_empty() {
+                columns.push((*tag, aggregate::Rule::First));
+            }
+            columns.extend(rules.into_iter().map(|rule| (*tag, rule)));
+        }
+        let separator = options.value_separator.as_deref().unwrap_or("\\");
+        let (header, rows) = aggregate::aggregate(
+            &maps,
+            key,
+            &columns,
+            |column| options.column_name(column),
+            &defaults,
+            separator,
+        );
+        let mut writer = csv_writer(&args, open_output(&args)?);
+        writer.write_record(&header)?;
+        for row in rows {
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    if let Some(column) = &summary {
+        let table = summary::summarize(
+            maps.iter().map(|(_, map)| map),
+            column,
+            &options.column_name(column),
+            summary_month.as_ref(),
+            &defaults,
+        );
+        let mut writer = csv_writer(&args, open_output(&args)?);
+        for row in table {
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    if args.format == OutputFormat::DicomJson {
+        if tags.iter().any(|c| matches!(c, Column::Derived(_))) {
+            warn!("Derived columns are not included in the DICOM JSON output");
+        }
+        let inputs: Vec<_> = maps.iter().map(|(input, _)| input.as_path()).collect();
+        let writer = open_output(&args)?;
+        return json::write_dicom_json(writer, &inputs, read_until, &tags, &options);
+    }
+
+    if args.format == OutputFormat::Xlsx {
+        if args.append {
+            bail!("--append is not supported for the xlsx output");
+        }
+        let mut header = vec!["FileName".to_string()];
+        header.extend(tags.iter().map(|tag| options.column_name(tag)));
+        let numeric: Vec<_> = tags.iter().map(is_numeric_column).collect();
+        let mut n_truncated = 0;
+        let file_names: Vec<_> = maps
+            .iter()
+            .map(|(input, _)| file_name_column(input))
+            .collect();
+        let rows: Vec<_> = maps
+            .iter()
+            .zip(&file_names)
+            .map(|((_, map), file_name)| {
+                let mut row = vec![xlsx::Cell::Str(file_name.as_ref())];
+                for (tag, &numeric) in tags.iter().zip(&numeric) {
+                    let value = cell(map, tag, &defaults);
+                    // Excel rejects infinities and NaN
+                    row.push(match value.trim().parse::<f64>() {
+                        Ok(number) if numeric && number.is_finite() => xlsx::Cell::Number(number),
+                        _ => {
+                            let truncated = xlsx::truncate_cell(value);
+                            if truncated.len() < value.len() {
+                                n_truncated += 1;
+                            }
+                            xlsx::Cell::Str(truncated)
+                        }
+                    });
+                }
+                row
+            })
+            .collect();
+        let output: Box<dyn std::io::Write> = match &args.output {
+            Some(output) => Box::new(std::io::BufWriter::new(std::fs::File::create(output)?)),
+            None => Box::new(std::io::stdout().lock()),
+        };
+        if n_truncated > 0 {
+            warn!(
+                "Truncated {n_truncated} values to the {} characters allowed in a cell",
+                xlsx::MAX_CELL_LEN
+            );
+        }
+        xlsx::write_xlsx(output, &header, &rows)?;
+        return Ok(());
+    }
+
+    if args.format == OutputFormat::Table {
+        let mut header = vec!["FileName".to_string()];
+        header.extend(tags.iter().map(|tag| options.column_name(tag)));
+        let file_names: Vec<_> = maps
+            .iter()
+            .map(|(input, _)| file_name_column(input))
+            .collect();
+        let rows: Vec<_> = maps
+            .iter()
+            .zip(&file_names)
+            .map(|((_, map), file_name)| {
+                let mut row = vec![file_name.as_ref()];
+                row.extend(tags.iter().map(|tag| cell(map, tag, &defaults)));
+                row
+            })
+            .collect();
+        // values are only truncated when shown in a terminal
+        let max_width = (args.output.is_none() && std::io::stdout().is_terminal())
+            .then(terminal_size::terminal_size)
+            .flatten()
+            .map(|(width, _)| usize::from(width.0));
+        table::write_table(open_output(&args)?, &header, &rows, max_width)?;
+        return Ok(());
+    }
+
+    if args.value_counts {
+        let mut writer = csv_writer(&args, open_output(&args)?);
+        writer.write_record(["Tag", "Value", "Count"])?;
+        for tag in &tags {
+            let name = options.column_name(tag);
+            for (value, count) in value_counts(
+                maps.iter().map(|(_, map)| map),
+                tag,
+                &defaults,
+                args.case_fold_values,
+            ) {
+                writer.write_record([name.as_str(), value, &count.to_string()])?;
+            }
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    if args.distinct_values {
+        let separator = options.value_separator.as_deref().unwrap_or("\\");
+        let mut writer = csv_writer(&args, open_output(&args)?);
+        let header: Vec<_> = tags.iter().map(|tag| options.column_name(tag)).collect();
+        writer.write_record(&header)?;
+        let row: Vec<_> = tags
+            .iter()
+            .map(|tag| {
+                let counts = value_counts(
+                    maps.iter().map(|(_, map)| map),
+                    tag,
+                    &defaults,
+                    args.case_fold_values,
+                );
+                let mut values: Vec<_> = counts
+                    .into_iter()
+                    .map(|(value, _)| value)
+                    .filter(|value| !value.is_empty())
+                    .collect();
+                values.sort_unstable();
+                values.join(separator)
+            })
+            .collect();
+        writer.write_record(&row)?;
+        writer.flush()?;
+        return Ok(());
+    }
+
+    if args.length_stats {
+        let mut writer = csv_writer(&args, open_output(&args)?);
+        writer.write_record(["Tag", "MinLen", "MaxLen", "NonEmptyCount"])?;
+        let format = |len: Option<usize>| len.map(|len| len.to_string()).unwrap_or_default();
+        for tag in &tags {
+            let (min, max, count) = length_stats(maps.iter().map(|(_, map)| map), tag, &defaults);
+            writer.write_record([
+                options.column_name(tag),
+                format(min),
+                format(max),
+                count.to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    if let Some(json_output) = &args.json_output {
+        json::write_json(json_output, &maps, &tags, &defaults, &options)?;
+    }
+    if let Some(ndjson_output) = &args.ndjson_output {
+        json::write_ndjson(ndjson_output, &maps, &tags, &defaults, &options)?;
+    }
+
+    if args.transpose {
+        let mut writer = csv_writer(&args, open_output(&args)?);
+        let mut header = vec!["FileName".to_string()];
+        header.extend(
+            maps.iter()
+                .map(|(input, _)| file_name_column(input).into_owned()),
+        );
+        writer.write_record(&header)?;
+        for tag in &tags {
+            let mut row = vec![options.column_name(tag)];
+            row.extend(
+                maps.iter()
+                    .map(|(_, map)| cell(map, tag, &defaults).to_string()),
+            );
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+
+    if let Some(column) = &split_by {
+        return write_split_csv(&args, column, &maps, &tags, &defaults, &options);
+    }
+
+    // write as csv
+    let mut writer = open_csv_writer(&args, &tags, &options)?;
+    for (input, map) in maps {
+        write_csv_row(&mut writer, &input, &map, &tags, &defaults)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn report_partial_reads(options: &DumpOptions) {
+    let n = options
+        .partial_reads
+        .load(std::sync::atomic::Ordering::Relaxed);
+    if n > 0 {
+        eprintln!("{n} files were cut off by --max-read-bytes");
+    }
+}
+
+/// CSV writer with the quoting and the line terminator of the arguments
+fn csv_writer<W: std::io::Write>(args: &Args, writer: W) -> csv::Writer<W> {
+    let quote_style = match args.csv_quoting {
+        CsvQuoting::Necessary => csv::QuoteStyle::Necessary,
+        CsvQuoting::Always => csv::QuoteStyle::Always,
+        CsvQuoting::Never => csv::QuoteStyle::Never,
+    };
+    let terminator = match args.csv_terminator {
+        CsvTerminator::Lf => csv::Terminator::Any(b'\n'),
+        CsvTerminator::Crlf => csv::Terminator::CRLF,
+    };
+    csv::WriterBuilder::new()
+        .quote_style(quote_style)
+        .terminator(terminator)
+        .from_writer(writer)
+}
+
+/// Open the CSV output and write the header unless appending or disabled
+fn open_csv_writer(
+    args: &Args,
+    tags: &[Column],
+    options: &DumpOptions,
+) -> Result<csv::Writer<Box<dyn std::io::Write>>> {
+    let appending = is_appending(args);
+    let mut writer = csv_writer(args, open_output(args)?);
+    if !appending && !args.no_header {
+        let mut header = vec!["FileName".to_string()];
+        header.extend(tags.iter().map(|tag| options.column_name(tag)));
+        writer.write_record(&header)?;
+    }
+    Ok(writer)
+}
+
+/// Write the rows into a CSV file per value of `column` in the output directory
+fn write_split_csv(
+    args: &Args,
+    column: &Column,
+    maps: &[(PathBuf, Row)],
+    tags: &[Column],
+    defaults: &HashMap<Column, String>,
+    options: &DumpOptions,
+) -> Result<()> {
+    let dir = args.output.as_deref().unwrap();
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {dir:?}"))?;
+    // in the order of appearance, under the first spelling found with --case-fold-values
+    let mut groups: Vec<(&str, Vec<&(PathBuf, Row)>)> = Vec::new();
+    let mut group_index: HashMap<Cow<str>, usize> = HashMap::new();
+    for entry in maps {
+        let value = cell(&entry.1, column, defaults);
+        let key = if args.case_fold_values {
+            Cow::Owned(value.to_lowercase())
+        } else {
+            Cow::Borrowed(value)
+        };
+        let i = *group_index.entry(key).or_insert_with(|| {
+            groups.push((value, Vec::new()));
+            groups.len() - 1
+        });
+        groups[i].1.push(entry);
+    }
+    let values: Vec<_> = groups.iter().map(|(value, _)| *value).collect();
+    let stems = split::file_stems(&values, args.replace_separators_in_filename)?;
+
+    let mut header = vec!["FileName".to_string()];
+    header.extend(tags.iter().map(|tag| options.column_name(tag)));
+    for ((_, rows), stem) in groups.iter().zip(&stems) {
+        let output = dir.join(format!("{stem}.csv"));
+        let mut writer = csv_writer(args, open_output_to(args, Some(&output))?);
+        if !args.no_header {
+            writer.write_record(&header)?;
+        }
+        for (input, map) in rows {
+            write_csv_row(&mut writer, input, map, tags, defaults)?;
+        }
+        writer.flush()?;
+    }
+    if args.replace_separators_in_filename {
+        let output = dir.join(format!("{}.csv", split::MAPPING_STEM));
+        let mut writer = csv_writer(args, std::fs::File::create(&output)?);
+        writer.write_record([options.column_name(column).as_str(), "FileName"])?;
+        for (value, stem) in values.iter().zip(&stems) {
+            writer.write_record([*value, &format!("{stem}.csv")])?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn write_csv_row(
+    writer: &mut csv::Writer<impl std::io::Write>,
+    input: &Path,
+    map: &Row,
+    tags: &[Column],
+    defaults: &HashMap<Column, String>,
+) -> Result<()> {
+    let mut row = Vec::with_capacity(tags.len() + 1);
+    let file_name = file_name_column(input);
+    row.push(file_name.as_ref());
+    for tag in tags {
+        row.push(cell(map, tag, defaults));
+    }
+    writer.write_record(row.iter())?;
+    Ok(())
+}
+
+/// Create an empty directory for a unit test, unique to the test process.
+///
+/// CARGO_TARGET_TMPDIR is only set for the integration tests, so the temporary directory
+/// of the system is used otherwise.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let root = option_env!("CARGO_TARGET_TMPDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let dir = root.join(format!("szkdcm-{}-{name}", std::process::id()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_core::Tag;
+
+    #[test]
+    fn test_tag_to_alias() {
+        let tag = Tag(0x0010, 0x0010);
+        let alias = tag_to_alias(tag);
+        assert_eq!(alias, "PatientName");
+        let alias = tag_to_alias(Tag(0x6000, 0x0022));
+        assert_eq!(alias, "OverlayDescription");
+        let alias = tag_to_alias(Tag(0x6002, 0x0022));
+        assert_eq!(alias, "OverlayDescription(6002,0022)");
+    }
+
+    #[test]
+    fn test_tag_ext_from_str() {
+        let tag_str = "0010,0010";
+        let tag_ext: TagExt = tag_str.parse().unwrap();
+        assert_eq!(tag_ext.0, Tag(0x0010, 0x0010));
+        let tag_str = "PatientName";
+        let tag_ext: TagExt = tag_str.parse().unwrap();
+        assert_eq!(tag_ext.0, Tag(0x0010, 0x0010));
+    }
+
+    #[test]
+    fn test_split_tag_list() {
+        assert_eq!(split_tag_list("PatientID"), ["PatientID"]);
+        assert_eq!(split_tag_list("0010,0020"), ["0010,0020"]);
+        assert_eq!(split_tag_list("(0010,0020)"), ["(0010,0020)"]);
+        assert_eq!(
+            split_tag_list("PatientID, 00100010,Modality"),
+            ["PatientID", "00100010", "Modality"]
+        );
+        assert_eq!(split_tag_list("0008,0060=CT"), ["0008,0060=CT"]);
+        assert_eq!(
+            split_tag_list("Modality=CT,PatientID"),
+            ["Modality=CT", "PatientID"]
+        );
+        assert_eq!(split_tag_default("Modality=CT"), ("Modality", Some("CT")));
+        assert_eq!(split_tag_default("Modality="), ("Modality", Some("")));
+        assert_eq!(split_tag_default("Modality"), ("Modality", None));
+    }
+
+    #[test]
+    fn test_column_from_str() {
+        let column: Column = "ReferencedImageSequence/*/ReferencedSOPInstanceUID"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            column,
+            Column::Item {
+                sequence: tags::REFERENCED_IMAGE_SEQUENCE,
+                index: ItemIndex::All,
+                tag: tags::REFERENCED_SOP_INSTANCE_UID,
+            }
+        );
+        let column: Column = "00081140/2/00081155".parse().unwrap();
+        assert_eq!(
+            column.name(),
+            "ReferencedImageSequence/2/ReferencedSOPInstanceUID"
+        );
+        assert!(
+            "ReferencedImageSequence/x/ReferencedSOPInstanceUID"
+                .parse::<Column>()
+                .is_err()
+        );
+        assert!("ReferencedImageSequence/0".parse::<Column>().is_err());
+        let component = Column::Component {
+            tag: tags::IMAGE_TYPE,
+            index: 1,
+        };
+        assert_eq!("ImageType_2".parse::<Column>().unwrap(), component);
+        assert!("ImageType_0".parse::<Column>().is_err());
+        assert_eq!(
+            "PatientID_bytes".parse::<Column>().unwrap(),
+            Column::Length(tags::PATIENT_ID)
+        );
+        assert_eq!(
+            "ReferencedImageSequence_items".parse::<Column>().unwrap(),
+            Column::ItemCount(tags::REFERENCED_IMAGE_SEQUENCE)
+        );
+        for column in [
+            Column::Tag(tags::PATIENT_ID),
+            column,
+            Column::Derived(Derived::Encoding),
+            component,
+            Column::Coalesced(tags::SLICE_LOCATION),
+            Column::Length(tags::PATIENT_ID),
+            Column::ItemCount(tags::REFERENCED_IMAGE_SEQUENCE),
+        ] {
+            assert_eq!(column.spec().parse::<Column>().unwrap(), column);
+        }
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        assert_eq!(catch_panic(|| Ok(1)).unwrap(), 1);
+        let e = catch_panic::<()>(|| panic!("static message")).unwrap_err();
+        assert_eq!(e.to_string(), "Panicked: static message");
+        let e = catch_panic::<()>(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(e.to_string(), "Panicked: formatted 1");
+    }
+
+    #[test]
+    fn test_make_names_unique() {
+        let columns = [
+            Column::Tag(tags::PATIENT_ID),
+            Column::Tag(tags::SOP_INSTANCE_UID),
+            Column::Tag(tags::MODALITY),
+            Column::Tag(tags::ISSUER_OF_PATIENT_ID),
+        ];
+        let mut options = DumpOptions {
+            names: HashMap::from([
+                (columns[0], "ID".to_string()),
+                (columns[1], "ID".to_string()),
+                (columns[3], "ID".to_string()),
+            ]),
+            ..Default::default()
+        };
+        options.make_names_unique(&columns);
+        let names: Vec<_> = columns.iter().map(|c| options.column_name(c)).collect();
+        assert_eq!(names, ["ID", "ID_2", "Modality", "ID_3"]);
+        // renaming again is stable
+        options.make_names_unique(&columns);
+        let renamed: Vec<_> = columns.iter().map(|c| options.column_name(c)).collect();
+        assert_eq!(renamed, names);
+    }
+
+    #[test]
+    fn test_most_present_columns() {
+        let columns = [
+            Column::Tag(tags::PATIENT_ID),
+            Column::Tag(tags::MODALITY),
+            Column::Tag(tags::STUDY_DATE),
+            Column::Tag(tags::SERIES_NUMBER),
+        ];
+        let row =
+            |columns: &[Column]| -> Row { columns.iter().map(|c| (*c, String::new())).collect() };
+        let rows = [
+            row(&columns[1..]),
+            row(&columns[2..]),
+            row(&columns[..1]),
+            row(&columns[3..]),
+        ];
+        assert_eq!(
+            most_present_columns(&columns, rows.iter(), 2),
+            [columns[2], columns[3]]
+        );
+        // ties are broken by the original order
+        assert_eq!(
+            most_present_columns(&columns, rows.iter(), 3),
+            [columns[0], columns[2], columns[3]]
+        );
+        assert_eq!(most_present_columns(&columns, rows.iter(), 5), columns);
+    }
+
+    #[test]
+    fn test_parse_tag() {
+        assert_eq!(parse_tag("PatientName").unwrap(), tags::PATIENT_NAME);
+        assert_eq!(parse_tag("00100020").unwrap(), tags::PATIENT_ID);
+        assert_eq!(parse_tag("(0008,0060)").unwrap(), tags::MODALITY);
+        assert!(parse_tag("NoSuchKeyword").is_err());
+    }
+
+    #[test]
+    fn test_value_counts() {
+        let column = Column::Tag(tags::MODALITY);
+        let rows: Vec<Row> = ["CT", "ct", "MR", "CT"]
+            .iter()
+            .map(|value| Row::from([(column, value.to_string())]))
+            .collect();
+        let defaults = HashMap::new();
+        assert_eq!(
+            value_counts(rows.iter(), &column, &defaults, false),
+            [("CT", 2), ("MR", 1), ("ct", 1)]
+        );
+        assert_eq!(
+            value_counts(rows.iter(), &column, &defaults, true),
+            [("CT", 3), ("MR", 1)]
+        );
+    }
+
+    #[test]
+    fn test_archive_entry_file_names() {
+        use dicom_core::DataElement;
+        use dicom_dictionary_std::uids;
+        use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
+        let dir = test_dir("archive_file_names");
+        let archive = dir.join("study.zip");
+        let mut zip = archive::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        for (name, patient_id) in [("series1/IM0001", "A"), ("series2/IM0001", "B")] {
+            let obj = InMemDicomObject::from_element_iter([
+                DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+                DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+                DataElement::new(tags::PATIENT_ID, VR::LO, patient_id),
+            ]);
+            let file = obj
+                .with_meta(
+                    FileMetaTableBuilder::new().transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN),
+                )
+                .unwrap();
+            let mut bytes = Vec::new();
+            file.write_all(&mut bytes).unwrap();
+            zip.add(name, &bytes).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![archive],
+            tag: vec!["PatientID".to_string()],
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        main(args).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(output).unwrap(),
+            "FileName,PatientID\nseries1/IM0001,A\nseries2/IM0001,B\n"
+        );
+    }
+
+    #[test]
+    fn test_is_sequence_tag() {
+        assert!(is_sequence_tag(tags::REFERENCED_IMAGE_SEQUENCE));
+        assert!(!is_sequence_tag(tags::PATIENT_ID));
+        assert!(!is_sequence_tag(Tag(0x0029, 0x1010)));
+    }
+
+    #[test]
+    fn test_length_stats() {
+        let column = Column::Tag(tags::PATIENT_NAME);
+        let rows: Vec<Row> = ["Doe^John", "", "Ōta^Ken"]
+            .iter()
+            .map(|value| Row::from([(column, value.to_string())]))
+            .chain([Row::new()])
+            .collect();
+        let defaults = HashMap::new();
+        assert_eq!(
+            length_stats(rows.iter(), &column, &defaults),
+            (Some(7), Some(8), 2)
+        );
+        let defaults = HashMap::from([(column, "Unknown".to_string())]);
+        assert_eq!(
+            length_stats(rows.iter(), &column, &defaults),
+            (Some(7), Some(8), 4)
+        );
+        assert_eq!(
+            length_stats(rows[..0].iter(), &column, &defaults),
+            (None, None, 0)
+        );
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("4k"), Ok(4096));
+        assert_eq!(parse_size("2M"), Ok(2 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("").is_err());
+    }
+
+    #[test]
+    fn test_sample_files() {
+        let filenames: Vec<_> = (0..20)
+            .map(|i| PathBuf::from(format!("{i:02}.dcm")))
+            .collect();
+        let sample = sample_files(filenames.clone(), 0.25, 42);
+        assert_eq!(sample.len(), 5);
+        assert!(sample.is_sorted());
+        // the same files regardless of the input order
+        let reversed = filenames.iter().rev().cloned().collect();
+        let mut sample_reversed = sample_files(reversed, 0.25, 42);
+        sample_reversed.reverse();
+        assert_eq!(sample, sample_reversed);
+        assert_ne!(sample, sample_files(filenames.clone(), 0.25, 43));
+        assert_eq!(sample_files(filenames.clone(), 1.0, 42), filenames);
+        assert!(parse_fraction("0").is_err());
+        assert!(parse_fraction("1.5").is_err());
+    }
+
+    #[test]
+    fn test_format_value() {
+        use dicom_core::{DataElement, PrimitiveValue, VR};
+        let elm: InMemElement = DataElement::new(
+            Tag(0x0028, 0x0030),
+            VR::DS,
+            PrimitiveValue::Strs(["0.5".to_string(), "0.25".to_string()].into()),
+        );
+        assert_eq!(format_value(&elm, None, true), "0.5\\0.25");
+        assert_eq!(format_value(&elm, Some("|"), true), "0.5|0.25");
+
+        // a single value containing a backslash must not be split
+        let elm: InMemElement = DataElement::new(
+            Tag(0x0008, 0x0081),
+            VR::ST,
+            PrimitiveValue::from("C:\\dicom"),
+        );
+        assert_eq!(format_value(&elm, Some("|"), true), "C:\\dicom");
+
+        let elm: InMemElement = DataElement::new(
+            Tag(0x0008, 0x0008),
+            VR::CS,
+            PrimitiveValue::Strs([" ORIGINAL".to_string(), "PRIMARY\0".to_string()].into()),
+        );
+        assert_eq!(format_value(&elm, None, true), "ORIGINAL\\PRIMARY");
+        assert_eq!(format_value(&elm, Some("|"), false), " ORIGINAL|PRIMARY\0");
+    }
+
+    #[test]
+    fn test_truncate_value() {
+        let value = || "abcdefghij".to_string();
+        assert_eq!(truncate_value(value(), 10, TruncateMode::End), "abcdefghij");
+        assert_eq!(truncate_value(value(), 5, TruncateMode::End), "abcd…");
+        assert_eq!(truncate_value(value(), 6, TruncateMode::Middle), "abc…ij");
+        assert_eq!(truncate_value(value(), 1, TruncateMode::Middle), "…");
+        assert_eq!(truncate_value(value(), 0, TruncateMode::End), "");
+        assert_eq!(truncate_value(value(), 0, TruncateMode::Middle), "");
+        assert_eq!(truncate_value(String::new(), 0, TruncateMode::End), "");
+    }
+
+    #[test]
+    fn test_read_input_list() {
+        let path = test_dir("input_list").join("input_list.txt");
+        std::fs::write(&path, "# comment\n a.dcm \n\ndir/b.dcm\n").unwrap();
+        let inputs = read_input_list(&path).unwrap();
+        assert_eq!(inputs, [PathBuf::from("a.dcm"), PathBuf::from("dir/b.dcm")]);
+    }
+
+    #[test]
+    fn test_union_columns() {
+        let row = |tags: &[Tag]| -> Row {
+            tags.iter()
+                .map(|tag| (Column::Tag(*tag), String::new()))
+                .collect()
+        };
+        let rows = [
+            row(&[tags::PATIENT_ID, tags::MODALITY]),
+            row(&[tags::PATIENT_ID, tags::ACCESSION_NUMBER, tags::ROWS]),
+        ];
+        let requested = [Column::Tag(tags::ROWS)];
+        let union = |order| -> Vec<_> {
+            union_columns(rows.iter(), &requested, order)
+                .iter()
+                .map(Column::name)
+                .collect()
+        };
+        assert_eq!(
+            union(TagOrder::GroupElement),
+            ["AccessionNumber", "Modality", "PatientID"]
+        );
+        assert_eq!(
+            union(TagOrder::FirstSeen),
+            ["Modality", "PatientID", "AccessionNumber"]
+        );
+        assert_eq!(
+            union(TagOrder::Alphabetical),
+            ["AccessionNumber", "Modality", "PatientID"]
+        );
+    }
+
+    #[test]
+    fn test_presets_have_aliases() {
+        for preset in Preset::value_variants() {
+            for tag in preset.tags() {
+                assert_ne!(tag_to_alias(*tag), tag.to_string());
+            }
+        }
     }
 }
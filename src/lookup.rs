@@ -0,0 +1,62 @@
+use crate::Column;
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A two-column table mapping values of a column to labels, passing through the others
+#[derive(Debug, Default)]
+pub(crate) struct Lookup {
+    labels: HashMap<String, String>,
+}
+
+impl Lookup {
+    /// Read `VALUE,LABEL` rows (without a header) from a CSV file
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open the lookup table {path:?}"))?;
+        Self::from_reader(file).with_context(|| format!("Invalid lookup table {path:?}"))
+    }
+
+    fn from_reader(reader: impl std::io::Read) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(reader);
+        let mut labels = HashMap::new();
+        for (i, record) in reader.records().enumerate() {
+            let record = record?;
+            let [value, label] = record.iter().collect::<Vec<_>>()[..] else {
+                bail!("line {}: expected VALUE,LABEL", i + 1);
+            };
+            labels.insert(value.to_string(), label.to_string());
+        }
+        Ok(Lookup { labels })
+    }
+
+    pub fn apply(&self, value: String) -> String {
+        self.labels.get(&value).cloned().unwrap_or(value)
+    }
+}
+
+/// Parse `TAG:FILE` given to --map
+pub(crate) fn parse_map_arg(s: &str) -> Result<(Column, Lookup)> {
+    let (tag, path) = s
+        .split_once(':')
+        .with_context(|| format!("Invalid map (expected TAG:FILE): {s}"))?;
+    Ok((tag.parse()?, Lookup::load(Path::new(path))?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let lookup = Lookup::from_reader(&b"CHEST,Chest\nHEAD,\"Head, neck\"\n"[..]).unwrap();
+        assert_eq!(lookup.apply("CHEST".to_string()), "Chest");
+        assert_eq!(lookup.apply("HEAD".to_string()), "Head, neck");
+        assert_eq!(lookup.apply("KNEE".to_string()), "KNEE");
+        assert!(Lookup::from_reader(&b"CHEST\n"[..]).is_err());
+        assert!(parse_map_arg("BodyPartExamined").is_err());
+    }
+}
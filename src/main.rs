@@ -1,9 +1,9 @@
 use anyhow::Result;
-use szkdcm::Args;
 use clap::Parser;
+use szkdcm::Args;
 
 fn main() -> Result<()> {
     env_logger::init();
     let args = Args::parse();
     szkdcm::main(args)
-}
\ No newline at end of file
+}
@@ -0,0 +1,97 @@
+use anyhow::{Context, Result, bail};
+use dicom_core::Tag;
+use dicom_encoding::TransferSyntaxIndex;
+use dicom_object::file::ReadPreamble;
+use dicom_object::{DefaultDicomObject, FileMetaTable, OpenFileOptions};
+use dicom_parser::dataset::{DataSetReader, DataToken};
+use dicom_transfer_syntax_registry::TransferSyntaxRegistry;
+use std::cell::Cell;
+use std::io::Read;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Open a DICOM file reading at most its first `max_bytes` bytes.
+///
+/// A file cut off by the limit is read up to the last complete top-level element,
+/// so that the tags not yet reached are absent.
+/// Returns the object and whether the file was cut off.
+pub(crate) fn open_partial(
+    input: &Path,
+    read_until: Option<Tag>,
+    max_bytes: u64,
+) -> Result<(DefaultDicomObject, bool)> {
+    let mut data = Vec::new();
+    // one more byte tells whether anything is left
+    crate::open_input(input)
+        .with_context(|| format!("Failed to open {input:?}"))?
+        .take(max_bytes.saturating_add(1))
+        .read_to_end(&mut data)?;
+    let cut_off = data.len() as u64 > max_bytes;
+    if data.get(128..132) != Some(b"DICM") {
+        bail!("{input:?} is not a DICOM file (missing DICM magic code)");
+    }
+    if cut_off {
+        data.truncate(max_bytes as usize);
+        let end = complete_len(&data[128..]).with_context(|| {
+            format!("The file meta group of {input:?} is longer than {max_bytes} bytes")
+        })?;
+        data.truncate(128 + end);
+    }
+    let options = match read_until {
+        Some(tag) => OpenFileOptions::new().read_until(tag),
+        None => OpenFileOptions::new().read_all(),
+    };
+    let obj = options
+        .read_preamble(ReadPreamble::Always)
+        .from_reader(&data[..])
+        .with_context(|| format!("Failed to open {input:?}"))?;
+    Ok((obj, cut_off))
+}
+
+/// Length of `data` (starting with the magic code) up to the end of the last
+/// complete top-level element, failing if the file meta group is incomplete
+fn complete_len(data: &[u8]) -> Result<usize> {
+    let position = Rc::new(Cell::new(0));
+    let mut reader = CountingReader {
+        data,
+        position: position.clone(),
+    };
+    let meta = FileMetaTable::from_reader(&mut reader)?;
+    let ts = TransferSyntaxRegistry
+        .get(meta.transfer_syntax())
+        .with_context(|| format!("Unknown transfer syntax: {}", meta.transfer_syntax()))?;
+    let mut end = position.get();
+    let mut depth = 0;
+    for token in DataSetReader::new_with_ts(reader, ts)? {
+        let Ok(token) = token else {
+            // the element being read was cut off
+            break;
+        };
+        match token {
+            DataToken::SequenceStart { .. } | DataToken::PixelSequenceStart => depth += 1,
+            DataToken::SequenceEnd => {
+                depth -= 1;
+                if depth == 0 {
+                    end = position.get();
+                }
+            }
+            DataToken::PrimitiveValue(_) if depth == 0 => end = position.get(),
+            _ => {}
+        }
+    }
+    Ok(end)
+}
+
+/// Reader of a byte slice sharing the number of bytes read
+struct CountingReader<'a> {
+    data: &'a [u8],
+    position: Rc<Cell<usize>>,
+}
+
+impl Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.data.read(buf)?;
+        self.position.set(self.position.get() + n);
+        Ok(n)
+    }
+}
@@ -0,0 +1,81 @@
+use dicom_core::Tag;
+use std::fmt;
+
+/// A tag with wildcard hex digits, for repeating groups (e.g. `60xx,0022`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TagPattern {
+    value: u32,
+    /// Bits which have to match `value`
+    mask: u32,
+}
+
+impl TagPattern {
+    /// Parse `ggggeeee`, `gggg,eeee`, or `(gggg,eeee)` with `x` as wildcard digits.
+    ///
+    /// Returns `None` if the pattern is malformed or has no wildcard digit.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_prefix('(').unwrap_or(s);
+        let s = s.strip_suffix(')').unwrap_or(s);
+        let digits: Vec<char> = s.chars().filter(|&c| c != ',').collect();
+        if digits.len() != 8 || (s.contains(',') && s.find(',') != Some(4)) {
+            return None;
+        }
+        let mut value = 0;
+        let mut mask = 0;
+        for c in digits {
+            value <<= 4;
+            mask <<= 4;
+            if c == 'x' || c == 'X' {
+                continue;
+            }
+            value |= c.to_digit(16)?;
+            mask |= 0xf;
+        }
+        (mask != u32::MAX).then_some(TagPattern { value, mask })
+    }
+
+    pub fn matches(&self, tag: Tag) -> bool {
+        let tag = (u32::from(tag.group()) << 16) | u32::from(tag.element());
+        tag & self.mask == self.value
+    }
+}
+
+impl fmt::Display for TagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in (0..8).rev() {
+            if i == 3 {
+                write!(f, ",")?;
+            }
+            let shift = i * 4;
+            if (self.mask >> shift) & 0xf == 0 {
+                write!(f, "x")?;
+            } else {
+                write!(f, "{:X}", (self.value >> shift) & 0xf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_pattern() {
+        let pattern = TagPattern::parse("60xx,0022").unwrap();
+        assert!(pattern.matches(Tag(0x6000, 0x0022)));
+        assert!(pattern.matches(Tag(0x601E, 0x0022)));
+        assert!(!pattern.matches(Tag(0x6000, 0x0010)));
+        assert!(!pattern.matches(Tag(0x5000, 0x0022)));
+        assert_eq!(pattern.to_string(), "60xx,0022");
+        assert_eq!(
+            TagPattern::parse("(50XX0010)").unwrap().to_string(),
+            "50xx,0010"
+        );
+        assert_eq!(TagPattern::parse("60000022"), None);
+        assert_eq!(TagPattern::parse("60x,0022"), None);
+        assert_eq!(TagPattern::parse("60xxx,022"), None);
+        assert_eq!(TagPattern::parse("PatientID"), None);
+    }
+}
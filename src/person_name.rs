@@ -0,0 +1,58 @@
+use clap::ValueEnum;
+
+/// Component of a person name (PN) value, in the order stored in DICOM
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameComponent {
+    Family,
+    Given,
+    Middle,
+    Prefix,
+    Suffix,
+}
+
+/// Order used when `--name-order` is not given
+pub(crate) const DEFAULT_ORDER: &[NameComponent] = &[NameComponent::Given, NameComponent::Family];
+
+/// Reformat a `Family^Given^Middle^Prefix^Suffix` value into space-separated components in `order`.
+///
+/// Empty components are dropped. Each of the alphabetic, ideographic, and phonetic
+/// representations (separated by `=`) is reformatted separately.
+pub(crate) fn normalize(value: &str, order: &[NameComponent]) -> String {
+    value
+        .trim_end_matches([' ', '\0'])
+        .split('=')
+        .map(|group| {
+            let components: Vec<_> = group.split('^').map(str::trim).collect();
+            order
+                .iter()
+                .filter_map(|&c| components.get(c as usize))
+                .filter(|c| !c.is_empty())
+                .copied()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("=")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use NameComponent::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("Doe^John", DEFAULT_ORDER), "John Doe");
+        assert_eq!(normalize("Doe^John^^Dr ", DEFAULT_ORDER), "John Doe");
+        assert_eq!(
+            normalize("Doe^John^Q^Dr^Jr", &[Prefix, Given, Middle, Family, Suffix]),
+            "Dr John Q Doe Jr"
+        );
+        assert_eq!(normalize("Doe", DEFAULT_ORDER), "Doe");
+        assert_eq!(
+            normalize("Yamada^Tarou=山田^太郎=やまだ^たろう", &[Family, Given]),
+            "Yamada Tarou=山田 太郎=やまだ たろう"
+        );
+        assert_eq!(normalize("", DEFAULT_ORDER), "");
+    }
+}
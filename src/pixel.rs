@@ -0,0 +1,162 @@
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use dicom_core::PrimitiveValue;
+use dicom_dictionary_std::tags;
+use dicom_object::DefaultDicomObject;
+
+/// Frames to compute pixel statistics over
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelFrames {
+    /// All frames of multi-frame images
+    #[default]
+    All,
+    /// Only the first frame
+    First,
+}
+
+/// Statistics of the stored pixel values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PixelStats {
+    pub min: i64,
+    pub max: i64,
+    pub mean: f64,
+}
+
+fn get_uint(obj: &DefaultDicomObject, tag: dicom_core::Tag) -> Option<u32> {
+    obj.get(tag)?.to_int::<u32>().ok()
+}
+
+/// Explicit VR Big Endian, which is retired
+const EXPLICIT_VR_BIG_ENDIAN: &str = "1.2.840.10008.1.2.2";
+
+/// The value in the low `bits_stored` bits of a sample, sign-extended if `signed`
+fn stored_value(sample: u64, bits_stored: u32, signed: bool) -> i64 {
+    let unused = 64 - bits_stored;
+    if signed {
+        ((sample << unused) as i64) >> unused
+    } else {
+        ((sample << unused) >> unused) as i64
+    }
+}
+
+/// Compute statistics of the stored (not rescaled) pixel values.
+///
+/// Only native (uncompressed) pixel data is supported.
+/// The bits other than the BitsStored ones are ignored.
+/// Returns `None` if the file has no pixel data or the pixel data is encapsulated.
+pub(crate) fn pixel_stats(
+    obj: &DefaultDicomObject,
+    frames: PixelFrames,
+) -> Result<Option<PixelStats>> {
+    let Some(pixel_data) = obj.get(tags::PIXEL_DATA) else {
+        return Ok(None);
+    };
+    let Some(value) = pixel_data.value().primitive() else {
+        // encapsulated pixel data requires decoding
+        return Ok(None);
+    };
+    let rows = get_uint(obj, tags::ROWS).unwrap_or_default() as usize;
+    let columns = get_uint(obj, tags::COLUMNS).unwrap_or_default() as usize;
+    let samples_per_pixel = get_uint(obj, tags::SAMPLES_PER_PIXEL).unwrap_or(1) as usize;
+    let bits_allocated = get_uint(obj, tags::BITS_ALLOCATED).unwrap_or_default();
+    let bits_stored = get_uint(obj, tags::BITS_STORED)
+        .filter(|bits| (1..=bits_allocated).contains(bits))
+        .unwrap_or(bits_allocated);
+    let signed = get_uint(obj, tags::PIXEL_REPRESENTATION) == Some(1);
+    // raw bytes are in the byte order of the transfer syntax
+    let big_endian = obj.meta().transfer_syntax().trim_end_matches('\0') == EXPLICIT_VR_BIG_ENDIAN;
+
+    let raw: Vec<u64> = match (value, bits_allocated) {
+        (PrimitiveValue::U8(bytes), 8) => bytes.iter().map(|&b| b as u64).collect(),
+        (PrimitiveValue::U8(bytes), 16) => bytes
+            .chunks_exact(2)
+            .map(|b| {
+                let b = [b[0], b[1]];
+                let v = if big_endian {
+                    u16::from_be_bytes(b)
+                } else {
+                    u16::from_le_bytes(b)
+                };
+                v as u64
+            })
+            .collect(),
+        (PrimitiveValue::U16(values), 16) => values.iter().map(|&v| v as u64).collect(),
+        (PrimitiveValue::U8(bytes), 32) => bytes
+            .chunks_exact(4)
+            .map(|b| {
+                let b = [b[0], b[1], b[2], b[3]];
+                let v = if big_endian {
+                    u32::from_be_bytes(b)
+                } else {
+                    u32::from_le_bytes(b)
+                };
+                v as u64
+            })
+            .collect(),
+        (PrimitiveValue::U32(values), 32) => values.iter().map(|&v| v as u64).collect(),
+        _ => bail!("Unsupported pixel data with {bits_allocated} bits allocated"),
+    };
+    let samples: Vec<i64> = raw
+        .into_iter()
+        .map(|v| stored_value(v, bits_stored, signed))
+        .collect();
+
+    let samples = match frames {
+        PixelFrames::All => &samples[..],
+        PixelFrames::First => {
+            let frame_len = rows * columns * samples_per_pixel;
+            &samples[..frame_len.min(samples.len())]
+        }
+    };
+    if samples.is_empty() {
+        return Ok(None);
+    }
+    let min = *samples.iter().min().unwrap();
+    let max = *samples.iter().max().unwrap();
+    let mean = samples.iter().map(|&v| v as f64).sum::<f64>() / samples.len() as f64;
+    Ok(Some(PixelStats { min, max, mean }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_core::{DataElement, VR};
+    use dicom_dictionary_std::uids;
+    use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
+
+    #[test]
+    fn test_stored_value() {
+        assert_eq!(stored_value(0x0fff, 12, true), -1);
+        assert_eq!(stored_value(0x0fff, 12, false), 4095);
+        assert_eq!(stored_value(0xf005, 12, true), 5);
+        assert_eq!(stored_value(0xffff, 16, true), -1);
+        assert_eq!(stored_value(0x80, 8, false), 128);
+    }
+
+    #[test]
+    fn test_pixel_stats_big_endian() {
+        let image = |signed: u16| {
+            let us = |tag, value: u16| DataElement::new(tag, VR::US, PrimitiveValue::from(value));
+            InMemDicomObject::from_element_iter([
+                DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+                DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+                us(tags::ROWS, 1),
+                us(tags::COLUMNS, 3),
+                us(tags::BITS_ALLOCATED, 16),
+                us(tags::BITS_STORED, 12),
+                us(tags::PIXEL_REPRESENTATION, signed),
+                DataElement::new(
+                    tags::PIXEL_DATA,
+                    VR::OB,
+                    PrimitiveValue::from(vec![0x0f, 0xff, 0x00, 0x01, 0xf0, 0x05]),
+                ),
+            ])
+            .with_meta(FileMetaTableBuilder::new().transfer_syntax(EXPLICIT_VR_BIG_ENDIAN))
+            .unwrap()
+        };
+        let stats = pixel_stats(&image(1), PixelFrames::All).unwrap().unwrap();
+        assert_eq!((stats.min, stats.max), (-1, 5));
+        let stats = pixel_stats(&image(0), PixelFrames::All).unwrap().unwrap();
+        assert_eq!((stats.min, stats.max), (1, 4095));
+    }
+}
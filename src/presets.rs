@@ -0,0 +1,91 @@
+use clap::ValueEnum;
+use dicom_core::Tag;
+use dicom_dictionary_std::tags;
+
+/// Curated tag lists for common use cases
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Patient demographics
+    Patient,
+    /// Study level identifiers and descriptions
+    Study,
+    /// CT acquisition and reconstruction parameters
+    Ct,
+    /// MR acquisition parameters
+    Mr,
+}
+
+const PATIENT: &[Tag] = &[
+    tags::PATIENT_NAME,
+    tags::PATIENT_ID,
+    tags::PATIENT_BIRTH_DATE,
+    tags::PATIENT_SEX,
+    tags::PATIENT_AGE,
+    tags::PATIENT_SIZE,
+    tags::PATIENT_WEIGHT,
+];
+
+const STUDY: &[Tag] = &[
+    tags::STUDY_INSTANCE_UID,
+    tags::STUDY_ID,
+    tags::STUDY_DATE,
+    tags::STUDY_TIME,
+    tags::ACCESSION_NUMBER,
+    tags::STUDY_DESCRIPTION,
+    tags::REFERRING_PHYSICIAN_NAME,
+];
+
+const CT: &[Tag] = &[
+    tags::MODALITY,
+    tags::MANUFACTURER,
+    tags::MANUFACTURER_MODEL_NAME,
+    tags::BODY_PART_EXAMINED,
+    tags::KVP,
+    tags::X_RAY_TUBE_CURRENT,
+    tags::EXPOSURE_TIME,
+    tags::SLICE_THICKNESS,
+    tags::CONVOLUTION_KERNEL,
+    tags::ROWS,
+    tags::COLUMNS,
+    tags::PIXEL_SPACING,
+    tags::RESCALE_INTERCEPT,
+    tags::RESCALE_SLOPE,
+];
+
+const MR: &[Tag] = &[
+    tags::MODALITY,
+    tags::MANUFACTURER,
+    tags::MANUFACTURER_MODEL_NAME,
+    tags::BODY_PART_EXAMINED,
+    tags::MAGNETIC_FIELD_STRENGTH,
+    tags::SCANNING_SEQUENCE,
+    tags::SEQUENCE_VARIANT,
+    tags::REPETITION_TIME,
+    tags::ECHO_TIME,
+    tags::INVERSION_TIME,
+    tags::FLIP_ANGLE,
+    tags::SLICE_THICKNESS,
+    tags::SPACING_BETWEEN_SLICES,
+    tags::ROWS,
+    tags::COLUMNS,
+    tags::PIXEL_SPACING,
+];
+
+/// Identifiers of the patient/study/series hierarchy, prepended with `--hierarchy`
+pub(crate) const HIERARCHY: &[Tag] = &[
+    tags::PATIENT_ID,
+    tags::STUDY_INSTANCE_UID,
+    tags::SERIES_INSTANCE_UID,
+];
+
+impl Preset {
+    /// Tags included in the preset
+    pub fn tags(self) -> &'static [Tag] {
+        match self {
+            Preset::Patient => PATIENT,
+            Preset::Study => STUDY,
+            Preset::Ct => CT,
+            Preset::Mr => MR,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+
+/// Wall-clock time spent in each stage of a run
+pub(crate) struct Profile {
+    enabled: bool,
+    start: Instant,
+    current: (&'static str, Instant),
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl Profile {
+    pub fn new(enabled: bool) -> Self {
+        let now = Instant::now();
+        Profile {
+            enabled,
+            start: now,
+            current: ("setup", now),
+            stages: Vec::new(),
+        }
+    }
+
+    /// End the current stage and start `name`
+    pub fn start(&mut self, name: &'static str) {
+        let now = Instant::now();
+        let (current, since) = std::mem::replace(&mut self.current, (name, now));
+        self.stages.push((current, now - since));
+    }
+
+    /// Print the time of each stage to stderr
+    pub fn report(mut self) {
+        if !self.enabled {
+            return;
+        }
+        self.start("");
+        let width = self
+            .stages
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        eprintln!("Profile:");
+        for (name, duration) in &self.stages {
+            eprintln!("  {name:<width$}  {:>9.3}s", duration.as_secs_f64());
+        }
+        let total = self.start.elapsed().as_secs_f64();
+        eprintln!("  {:<width$}  {total:>9.3}s", "total");
+    }
+}
@@ -0,0 +1,47 @@
+use dicom_core::Tag;
+use dicom_dictionary_std::tags;
+
+/// Current tags and the retired tags they replaced.
+///
+/// The retired tags are written as numbers since their constants are deprecated.
+const EQUIVALENTS: &[(Tag, Tag)] = &[
+    // ImagePosition
+    (tags::IMAGE_POSITION_PATIENT, Tag(0x0020, 0x0030)),
+    // ImageOrientation
+    (tags::IMAGE_ORIENTATION_PATIENT, Tag(0x0020, 0x0035)),
+    // Location
+    (tags::SLICE_LOCATION, Tag(0x0020, 0x0050)),
+    // ReasonForStudy
+    (
+        tags::REASON_FOR_THE_REQUESTED_PROCEDURE,
+        Tag(0x0032, 0x1030),
+    ),
+];
+
+/// The retired tag replaced by `tag`, or the current tag replacing `tag`
+pub(crate) fn equivalent(tag: Tag) -> Option<Tag> {
+    EQUIVALENTS.iter().find_map(|&(current, retired)| {
+        if tag == current {
+            Some(retired)
+        } else if tag == retired {
+            Some(current)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equivalent() {
+        assert_eq!(
+            equivalent(tags::IMAGE_POSITION_PATIENT),
+            Some(Tag(0x0020, 0x0030))
+        );
+        assert_eq!(equivalent(Tag(0x0020, 0x0050)), Some(tags::SLICE_LOCATION));
+        assert_eq!(equivalent(tags::PATIENT_NAME), None);
+    }
+}
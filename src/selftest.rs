@@ -0,0 +1,127 @@
+use crate::{Column, Derived, extract};
+use anyhow::{Context, Result, bail};
+use dicom_core::value::PixelFragmentSequence;
+use dicom_core::{DataElement, PrimitiveValue, VR};
+use dicom_dictionary_std::{tags, uids};
+use dicom_object::mem::InMemElement;
+use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
+use std::path::Path;
+
+/// A fixture written with a transfer syntax and the values expected from it
+struct Case {
+    name: &'static str,
+    transfer_syntax: &'static str,
+    encapsulated: bool,
+    encoding: &'static str,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "Explicit VR Little Endian",
+        transfer_syntax: uids::EXPLICIT_VR_LITTLE_ENDIAN,
+        encapsulated: false,
+        encoding: "ExplicitVRLittleEndian",
+    },
+    Case {
+        name: "Implicit VR Little Endian",
+        transfer_syntax: uids::IMPLICIT_VR_LITTLE_ENDIAN,
+        encapsulated: false,
+        encoding: "ImplicitVRLittleEndian",
+    },
+    Case {
+        name: "Explicit VR Big Endian",
+        transfer_syntax: "1.2.840.10008.1.2.2",
+        encapsulated: false,
+        encoding: "ExplicitVRBigEndian",
+    },
+    Case {
+        name: "JPEG Baseline (encapsulated)",
+        transfer_syntax: uids::JPEG_BASELINE8_BIT,
+        encapsulated: true,
+        encoding: "ExplicitVRLittleEndian",
+    },
+];
+
+/// Write a fixture for each of the common transfer syntaxes, extract it,
+/// and print whether the known values are read back
+pub fn main() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("szkdcm-selftest-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create a temporary directory {dir:?}"))?;
+    let mut failed = 0;
+    for case in CASES {
+        match check(case, &dir.join("fixture.dcm")) {
+            Ok(()) => println!("PASS {}", case.name),
+            Err(e) => {
+                println!("FAIL {}: {e:#}", case.name);
+                failed += 1;
+            }
+        }
+    }
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        log::warn!("Failed to remove {dir:?}: {e}");
+    }
+    println!("{} of {} checks passed", CASES.len() - failed, CASES.len());
+    if failed > 0 {
+        bail!("Self-test failed");
+    }
+    Ok(())
+}
+
+fn check(case: &Case, path: &Path) -> Result<()> {
+    write_fixture(case, path).context("Failed to write the fixture")?;
+    let expected = [
+        (Column::Tag(tags::PATIENT_ID), "SELFTEST"),
+        (Column::Tag(tags::PATIENT_NAME), "Doe^John"),
+        (Column::Tag(tags::ROWS), "2"),
+        (Column::Tag(tags::PIXEL_SPACING), "0.5\\0.25"),
+        (Column::Derived(Derived::Encoding), case.encoding),
+        (
+            Column::Derived(Derived::NumFragments),
+            if case.encapsulated { "2" } else { "" },
+        ),
+        (
+            Column::Derived(Derived::PixelDataSizeOk),
+            if case.encapsulated { "" } else { "true" },
+        ),
+    ];
+    let columns: Vec<_> = expected.iter().map(|(column, _)| *column).collect();
+    let row = extract(path, tags::PIXEL_DATA, &columns)?;
+    for (column, value) in expected {
+        let actual = row.get(&column).map(String::as_str).unwrap_or_default();
+        if actual != value {
+            bail!("{} is {actual:?} (expected {value:?})", column.name());
+        }
+    }
+    Ok(())
+}
+
+fn write_fixture(case: &Case, path: &Path) -> Result<()> {
+    let pixel_data: InMemElement = if case.encapsulated {
+        DataElement::new(
+            tags::PIXEL_DATA,
+            VR::OB,
+            PixelFragmentSequence::new(vec![0, 8], vec![vec![0xff; 8]; 2]),
+        )
+    } else {
+        DataElement::new(
+            tags::PIXEL_DATA,
+            VR::OW,
+            PrimitiveValue::U16((0..4).collect()),
+        )
+    };
+    let obj = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+        DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+        DataElement::new(tags::PATIENT_NAME, VR::PN, "Doe^John"),
+        DataElement::new(tags::PATIENT_ID, VR::LO, "SELFTEST"),
+        DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2u16)),
+        DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2u16)),
+        DataElement::new(tags::PIXEL_SPACING, VR::DS, "0.5\\0.25"),
+        DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16u16)),
+        pixel_data,
+    ]);
+    obj.with_meta(FileMetaTableBuilder::new().transfer_syntax(case.transfer_syntax))?
+        .write_to_file(path)?;
+    Ok(())
+}
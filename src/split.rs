@@ -0,0 +1,158 @@
+use anyhow::{Result, bail};
+use std::collections::HashSet;
+
+/// Longest file stem written by --split-by (in characters)
+const MAX_STEM_LEN: usize = 100;
+
+/// Stem of the file listing the values and their file names
+pub(crate) const MAPPING_STEM: &str = "filenames";
+
+/// Stem of the file for an empty value
+const EMPTY_STEM: &str = "_";
+
+/// Device names reserved on Windows, besides `COM1`-`COM9` and `LPT1`-`LPT9`
+const RESERVED_NAMES: &[&str] = &["CON", "PRN", "AUX", "NUL"];
+
+/// File stems for the distinct `values` of the --split-by tag, in the same order.
+///
+/// With `replace`, characters not allowed in file names are replaced with `_`, long
+/// values are truncated, and reserved device names are prefixed with `_`,
+/// otherwise such a value is an error.
+/// Stems equal (ignoring case) to a previous one are suffixed with `_2`, `_3`, ...
+pub(crate) fn file_stems(values: &[&str], replace: bool) -> Result<Vec<String>> {
+    let mut used = HashSet::from([MAPPING_STEM.to_string()]);
+    let mut stems = Vec::with_capacity(values.len());
+    for value in values {
+        let stem = if value.is_empty() {
+            EMPTY_STEM.to_string()
+        } else if is_safe(value) {
+            value.to_string()
+        } else if replace {
+            sanitize(value)
+        } else {
+            bail!(
+                "{value:?} cannot be used as a file name with --split-by \
+                 (use --replace-separators-in-filename)"
+            );
+        };
+        let mut unique = stem.clone();
+        let mut n = 1;
+        while !used.insert(unique.to_lowercase()) {
+            n += 1;
+            unique = format!("{stem}_{n}");
+        }
+        stems.push(unique);
+    }
+    Ok(stems)
+}
+
+fn is_unsafe_char(c: char) -> bool {
+    c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+}
+
+/// Whether `value` is a device name on Windows, which is also the case with an extension
+/// (e.g. `NUL.csv`)
+fn is_reserved(value: &str) -> bool {
+    let base = value.split('.').next().unwrap_or_default().trim_end();
+    let numbered = |prefix: &str| {
+        base.len() == 4
+            && base.is_ascii()
+            && base[..3].eq_ignore_ascii_case(prefix)
+            && matches!(base.as_bytes()[3], b'1'..=b'9')
+    };
+    RESERVED_NAMES
+        .iter()
+        .any(|name| base.eq_ignore_ascii_case(name))
+        || numbered("COM")
+        || numbered("LPT")
+}
+
+/// Whether `value` is a valid file name on the common file systems
+fn is_safe(value: &str) -> bool {
+    // Windows drops trailing dots and spaces
+    !value.chars().any(is_unsafe_char)
+        && !value.ends_with(['.', ' '])
+        && !value.starts_with([' ', '.'])
+        && value.chars().count() <= MAX_STEM_LEN
+        && !is_reserved(value)
+}
+
+fn sanitize(value: &str) -> String {
+    let mut stem: String = value
+        .chars()
+        .take(MAX_STEM_LEN)
+        .map(|c| if is_unsafe_char(c) { '_' } else { c })
+        .collect();
+    if stem.starts_with(['.', ' ']) {
+        stem.replace_range(..1, "_");
+    }
+    if stem.ends_with(['.', ' ']) {
+        let end = stem.len() - 1;
+        stem.replace_range(end.., "_");
+    }
+    if is_reserved(&stem) {
+        stem.insert(0, '_');
+        if stem.chars().count() > MAX_STEM_LEN {
+            stem.pop();
+        }
+    }
+    stem
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_stems() {
+        let long = "x".repeat(150);
+        let values = [
+            "1.2.840.10008",
+            "Doe^John",
+            "",
+            "CT/MR",
+            "CT_MR",
+            "ct_mr",
+            ".",
+            long.as_str(),
+        ];
+        let stems = file_stems(&values, true).unwrap();
+        assert_eq!(
+            stems[..7],
+            [
+                "1.2.840.10008",
+                "Doe^John",
+                "_",
+                "CT_MR",
+                "CT_MR_2",
+                "ct_mr_3",
+                "__2"
+            ]
+        );
+        assert_eq!(stems[7], "x".repeat(100));
+        assert!(file_stems(&["CT/MR"], false).is_err());
+        assert!(file_stems(&[long.as_str()], false).is_err());
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        let values = [
+            "CON", "nul", "Com1", "LPT9.txt", "AUX ", "COM0", "CONSOLE", "LPT10",
+        ];
+        assert_eq!(
+            file_stems(&values, true).unwrap(),
+            [
+                "_CON",
+                "_nul",
+                "_Com1",
+                "_LPT9.txt",
+                "AUX_",
+                "COM0",
+                "CONSOLE",
+                "LPT10"
+            ]
+        );
+        assert!(file_stems(&["CON"], false).is_err());
+        assert!(file_stems(&["COM0"], false).is_ok());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{Column, Row, cell, filter::parse_da};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Label of the month column for files without a valid date
+const UNDATED: &str = "Undated";
+
+/// Count the rows per value of `column` as `name,Count` rows, or as a table with
+/// a column per month (`YYYY-MM`) of `month_column` and the total if it is given
+pub(crate) fn summarize<'a>(
+    rows: impl Iterator<Item = &'a Row>,
+    column: &Column,
+    name: &str,
+    month_column: Option<&Column>,
+    defaults: &HashMap<Column, String>,
+) -> Vec<Vec<String>> {
+    let mut counts: BTreeMap<&str, BTreeMap<Option<String>, usize>> = BTreeMap::new();
+    for row in rows {
+        let month = month_column.and_then(|month_column| {
+            parse_da(cell(row, month_column, defaults)).map(|date| date.format("%Y-%m").to_string())
+        });
+        *counts
+            .entry(cell(row, column, defaults))
+            .or_default()
+            .entry(month)
+            .or_default() += 1;
+    }
+
+    if month_column.is_none() {
+        let mut table = vec![vec![name.to_string(), "Count".to_string()]];
+        for (value, months) in counts {
+            table.push(vec![
+                value.to_string(),
+                months.values().sum::<usize>().to_string(),
+            ]);
+        }
+        return table;
+    }
+    // `None` (undated) comes first in the order of `Option`
+    let months: BTreeSet<&Option<String>> = counts.values().flat_map(|m| m.keys()).collect();
+    let mut months: Vec<_> = months.into_iter().collect();
+    let undated = usize::from(months.first() == Some(&&None));
+    months.rotate_left(undated);
+    let mut header = vec![name.to_string()];
+    header.extend(
+        months
+            .iter()
+            .map(|month| month.as_deref().unwrap_or(UNDATED).to_string()),
+    );
+    header.push("Total".to_string());
+    let mut table = vec![header];
+    for (value, counts) in &counts {
+        let mut row = vec![value.to_string()];
+        row.extend(
+            months
+                .iter()
+                .map(|month| counts.get(*month).copied().unwrap_or(0).to_string()),
+        );
+        row.push(counts.values().sum::<usize>().to_string());
+        table.push(row);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_dictionary_std::tags;
+
+    #[test]
+    fn test_summarize() {
+        let modality = Column::Tag(tags::MODALITY);
+        let date = Column::Tag(tags::STUDY_DATE);
+        let rows: Vec<_> = [
+            ("CT", "20230105"),
+            ("CT", "20230220"),
+            ("MR", "20230110"),
+            ("CT", "20230131"),
+            ("MR", ""),
+        ]
+        .iter()
+        .map(|(m, d)| Row::from([(modality, m.to_string()), (date, d.to_string())]))
+        .collect();
+        let defaults = HashMap::new();
+        assert_eq!(
+            summarize(rows.iter(), &modality, "Modality", None, &defaults),
+            [["Modality", "Count"], ["CT", "3"], ["MR", "2"]]
+        );
+        assert_eq!(
+            summarize(rows.iter(), &modality, "Modality", Some(&date), &defaults),
+            [
+                ["Modality", "2023-01", "2023-02", "Undated", "Total"],
+                ["CT", "2", "1", "0", "3"],
+                ["MR", "1", "0", "1", "2"],
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{TruncateMode, truncate_value};
+use std::io::{self, Write};
+
+/// Narrowest width the columns are shrunk to (in characters)
+const MIN_COLUMN_WIDTH: usize = 4;
+
+const SEPARATOR: &str = " | ";
+
+/// Write the rows as a table with aligned columns under a header line.
+///
+/// With `max_width`, the widest columns are narrowed until the table fits,
+/// and the values of narrowed columns are truncated with an ellipsis.
+pub(crate) fn write_table(
+    mut out: impl Write,
+    header: &[String],
+    rows: &[Vec<&str>],
+    max_width: Option<usize>,
+) -> io::Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|name| name.chars().count()).collect();
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+    if let Some(max_width) = max_width {
+        fit_widths(&mut widths, max_width);
+    }
+
+    let header: Vec<_> = header.iter().map(String::as_str).collect();
+    write_line(&mut out, &header, &widths)?;
+    let rule: Vec<_> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    writeln!(out, "{}", rule.join("-+-"))?;
+    for row in rows {
+        write_line(&mut out, row, &widths)?;
+    }
+    out.flush()
+}
+
+/// Narrow the widest columns until the total width including the separators is
+/// at most `max_width`, or every column is at its minimum
+fn fit_widths(widths: &mut [usize], max_width: usize) {
+    let separators = SEPARATOR.len() * widths.len().saturating_sub(1);
+    let mut total = widths.iter().sum::<usize>() + separators;
+    while total > max_width {
+        let Some(widest) = widths.iter_mut().max_by_key(|width| **width) else {
+            return;
+        };
+        if *widest <= MIN_COLUMN_WIDTH {
+            return;
+        }
+        *widest -= 1;
+        total -= 1;
+    }
+}
+
+fn write_line(out: &mut impl Write, values: &[&str], widths: &[usize]) -> io::Result<()> {
+    let cells: Vec<_> = values
+        .iter()
+        .zip(widths)
+        .map(|(value, width)| {
+            // line breaks would break the alignment
+            let value = value.replace(['\n', '\r', '\t'], " ");
+            let value = truncate_value(value, *width, TruncateMode::End);
+            format!("{value:width$}")
+        })
+        .collect();
+    writeln!(out, "{}", cells.join(SEPARATOR).trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_table() {
+        let header = ["FileName".to_string(), "Desc".to_string()];
+        let rows = [vec!["a.dcm", "Chest CT"], vec!["long_name.dcm", "Head\nMR"]];
+        let mut out = Vec::new();
+        write_table(&mut out, &header, &rows, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "FileName      | Desc\n\
+             --------------+---------\n\
+             a.dcm         | Chest CT\n\
+             long_name.dcm | Head MR\n"
+        );
+        let mut out = Vec::new();
+        write_table(&mut out, &header, &rows, Some(20)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "FileName  | Desc\n\
+             ----------+---------\n\
+             a.dcm     | Chest CT\n\
+             long_nam… | Head MR\n"
+        );
+    }
+}
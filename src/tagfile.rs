@@ -0,0 +1,215 @@
+use crate::Column;
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde_json::Value;
+use std::path::Path;
+
+/// Format of a tag file
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagFileFormat {
+    /// One tag per line, `#` starts a comment
+    Lines,
+    /// JSON array of tags or `{"tag": ..., "name": ..., "transform": ...}` objects
+    Json,
+}
+
+impl TagFileFormat {
+    /// Guess the format from the file extension
+    fn detect(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => TagFileFormat::Json,
+            _ => TagFileFormat::Lines,
+        }
+    }
+}
+
+/// Transformation applied to the extracted values of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Transform {
+    Upper,
+    Lower,
+    Trim,
+}
+
+impl Transform {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "upper" => Some(Transform::Upper),
+            "lower" => Some(Transform::Lower),
+            "trim" => Some(Transform::Trim),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, value: String) -> String {
+        match self {
+            Transform::Upper => value.to_uppercase(),
+            Transform::Lower => value.to_lowercase(),
+            Transform::Trim => value.trim().to_string(),
+        }
+    }
+}
+
+/// A column read from a tag file, optionally renamed and transformed
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TagSpec {
+    pub column: Column,
+    pub name: Option<String>,
+    pub transform: Option<Transform>,
+}
+
+/// Read the columns listed in a tag file (the format is detected by the extension if `None`)
+pub(crate) fn read_tag_file(path: &Path, format: Option<TagFileFormat>) -> Result<Vec<TagSpec>> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    match format.unwrap_or_else(|| TagFileFormat::detect(path)) {
+        TagFileFormat::Lines => parse_lines(&content),
+        TagFileFormat::Json => {
+            parse_json(&content).with_context(|| format!("Invalid tag file {path:?}"))
+        }
+    }
+}
+
+/// Merge the columns of several tag files, later files taking precedence.
+///
+/// The columns are kept at their first position, and a name or transform given by
+/// a later entry replaces that of an earlier one for the same column. An entry
+/// without a name or transform keeps the ones given before.
+pub(crate) fn merge_tag_specs(files: impl IntoIterator<Item = Vec<TagSpec>>) -> Vec<TagSpec> {
+    let mut merged: Vec<TagSpec> = Vec::new();
+    for spec in files.into_iter().flatten() {
+        match merged
+            .iter_mut()
+            .find(|merged| merged.column == spec.column)
+        {
+            Some(merged) => {
+                if spec.name.is_some() {
+                    merged.name = spec.name;
+                }
+                if spec.transform.is_some() {
+                    merged.transform = spec.transform;
+                }
+            }
+            None => merged.push(spec),
+        }
+    }
+    merged
+}
+
+fn parse_lines(content: &str) -> Result<Vec<TagSpec>> {
+    let mut specs = Vec::new();
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        specs.push(TagSpec {
+            column: line.parse()?,
+            name: None,
+            transform: None,
+        });
+    }
+    Ok(specs)
+}
+
+fn parse_json(content: &str) -> Result<Vec<TagSpec>> {
+    let Value::Array(entries) = serde_json::from_str(content)? else {
+        bail!("Expected an array of tags");
+    };
+    let mut specs = Vec::new();
+    for entry in entries {
+        let spec = match entry {
+            Value::String(tag) => TagSpec {
+                column: tag.parse()?,
+                name: None,
+                transform: None,
+            },
+            Value::Object(object) => {
+                let field = |key: &str| match object.get(key) {
+                    None => Ok(None),
+                    Some(Value::String(s)) => Ok(Some(s.as_str())),
+                    Some(_) => Err(anyhow::anyhow!("Expected a string for {key:?}")),
+                };
+                let tag = field("tag")?.context("Missing \"tag\"")?;
+                let transform = match field("transform")? {
+                    Some(name) => Some(
+                        Transform::from_name(name)
+                            .with_context(|| format!("Unknown transform: {name}"))?,
+                    ),
+                    None => None,
+                };
+                TagSpec {
+                    column: tag.parse()?,
+                    name: field("name")?.map(String::from),
+                    transform,
+                }
+            }
+            entry => bail!("Expected a tag or an object: {entry}"),
+        };
+        specs.push(spec);
+    }
+    Ok(specs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_dictionary_std::tags;
+
+    #[test]
+    fn test_merge_tag_specs() {
+        let team =
+            parse_json(r#"[{"tag": "PatientID", "name": "ID", "transform": "upper"}, "Modality"]"#)
+                .unwrap();
+        let project = parse_json(
+            r#"[{"tag": "Modality", "name": "Mod"}, {"tag": "PatientID", "name": "Patient"}, "StudyDate"]"#,
+        )
+        .unwrap();
+        let merged = merge_tag_specs([team, project, parse_lines("Modality").unwrap()]);
+        assert_eq!(
+            merged,
+            [
+                TagSpec {
+                    column: Column::Tag(tags::PATIENT_ID),
+                    name: Some("Patient".to_string()),
+                    transform: Some(Transform::Upper),
+                },
+                TagSpec {
+                    column: Column::Tag(tags::MODALITY),
+                    name: Some("Mod".to_string()),
+                    transform: None,
+                },
+                TagSpec {
+                    column: Column::Tag(tags::STUDY_DATE),
+                    name: None,
+                    transform: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let specs =
+            parse_json(r#"["Modality", {"tag": "PatientID", "name": "ID", "transform": "upper"}]"#)
+                .unwrap();
+        assert_eq!(
+            specs,
+            [
+                TagSpec {
+                    column: Column::Tag(tags::MODALITY),
+                    name: None,
+                    transform: None,
+                },
+                TagSpec {
+                    column: Column::Tag(tags::PATIENT_ID),
+                    name: Some("ID".to_string()),
+                    transform: Some(Transform::Upper),
+                },
+            ]
+        );
+        assert!(parse_json(r#"[{"name": "ID"}]"#).is_err());
+        assert!(parse_json(r#"[{"tag": "PatientID", "transform": "reverse"}]"#).is_err());
+        assert!(parse_json(r#"{"tag": "PatientID"}"#).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::Column;
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// An external command transforming the values of a column.
+///
+/// The value is written to the stdin of the command followed by a newline, and
+/// the stdout without the trailing newline becomes the new value.
+#[derive(Debug)]
+pub(crate) struct ValueFilter {
+    command: String,
+    /// Outputs of the command by value, so that it runs once per distinct value
+    cache: Mutex<HashMap<String, String>>,
+}
+
+impl ValueFilter {
+    pub fn new(command: &str) -> Self {
+        ValueFilter {
+            command: command.to_string(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn apply(&self, value: &str) -> Result<String> {
+        if let Some(output) = self.cache.lock().unwrap().get(value) {
+            return Ok(output.clone());
+        }
+        let output = self
+            .run(value)
+            .with_context(|| format!("Value filter {:?} failed", self.command))?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(value.to_string(), output.clone());
+        Ok(output)
+    }
+
+    fn run(&self, value: &str) -> Result<String> {
+        let mut child = shell_command(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        match writeln!(stdin, "{value}") {
+            // the command may exit without reading its input
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+        drop(stdin);
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!("exited with {}", output.status);
+        }
+        let stdout = String::from_utf8(output.stdout).context("Output is not UTF-8")?;
+        let stdout = stdout.strip_suffix('\n').unwrap_or(&stdout);
+        Ok(stdout.strip_suffix('\r').unwrap_or(stdout).to_string())
+    }
+}
+
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
+
+/// Parse `TAG:COMMAND` given to --value-filter
+pub(crate) fn parse_value_filter_arg(s: &str) -> Result<(Column, ValueFilter)> {
+    let (tag, command) = s
+        .split_once(':')
+        .with_context(|| format!("Invalid value filter (expected TAG:COMMAND): {s}"))?;
+    Ok((tag.parse()?, ValueFilter::new(command)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom_dictionary_std::tags;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_value_filter() {
+        let (column, filter) = parse_value_filter_arg("PatientID:tr a-z A-Z").unwrap();
+        assert_eq!(column, Column::Tag(tags::PATIENT_ID));
+        assert_eq!(filter.apply("id1").unwrap(), "ID1");
+        assert_eq!(filter.apply("id1").unwrap(), "ID1");
+        assert_eq!(filter.apply("").unwrap(), "");
+        assert_eq!(filter.cache.lock().unwrap().len(), 2);
+        assert!(ValueFilter::new("exit 1").apply("id1").is_err());
+        assert!(parse_value_filter_arg("PatientID").is_err());
+    }
+}
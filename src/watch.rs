@@ -0,0 +1,72 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Tracks the files found by polling the input directories.
+///
+/// A new file is ready once its size and modification time are unchanged
+/// between two scans, so that files still being copied are not read.
+#[derive(Debug, Default)]
+pub(crate) struct Watcher {
+    /// Size and modification time of the new files at the previous scan
+    pending: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+    done: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    /// Start watching with the files which are already processed
+    pub fn new(processed: impl IntoIterator<Item = PathBuf>) -> Self {
+        Watcher {
+            pending: HashMap::new(),
+            done: processed.into_iter().collect(),
+        }
+    }
+
+    /// Update the state with the files found by a scan and return the ones ready to be processed
+    pub fn poll(&mut self, found: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut ready = Vec::new();
+        let mut pending = HashMap::new();
+        for path in found {
+            if self.done.contains(&path) {
+                continue;
+            }
+            // removed since the scan
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            let state = (metadata.len(), metadata.modified().ok());
+            if self.pending.get(&path) == Some(&state) {
+                self.done.insert(path.clone());
+                ready.push(path);
+            } else {
+                pending.insert(path, state);
+            }
+        }
+        self.pending = pending;
+        ready.sort();
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watcher() {
+        let dir = crate::test_dir("watcher");
+        let old = dir.join("old.dcm");
+        let new = dir.join("new.dcm");
+        std::fs::write(&old, b"old").unwrap();
+        std::fs::write(&new, b"new").unwrap();
+        let found = || vec![old.clone(), new.clone()];
+
+        let mut watcher = Watcher::new([old.clone()]);
+        assert!(watcher.poll(found()).is_empty());
+        // still being written
+        std::fs::write(&new, b"new file").unwrap();
+        assert!(watcher.poll(found()).is_empty());
+        assert_eq!(watcher.poll(found()), std::slice::from_ref(&new));
+        assert!(watcher.poll(found()).is_empty());
+    }
+}
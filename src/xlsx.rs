@@ -0,0 +1,168 @@
+use crate::archive::ZipWriter;
+use std::io::{self, Write};
+
+/// A cell of the worksheet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Cell<'a> {
+    Str(&'a str),
+    Number(f64),
+}
+
+/// Maximum number of characters in a cell
+pub(crate) const MAX_CELL_LEN: usize = 32767;
+
+/// Upper limit of the automatic column width (in characters)
+const MAX_COLUMN_WIDTH: usize = 60;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const WORKBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="szkdcm" sheetId="1" r:id="rId1"/></sheets></workbook>"#;
+
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><fonts count="1"><font><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/></cellXfs></styleSheet>"#;
+
+/// Write a single worksheet workbook with the header row frozen and the columns sized to their contents.
+///
+/// The parts of the workbook are stored uncompressed in the zip archive.
+pub(crate) fn write_xlsx(
+    output: impl Write,
+    header: &[String],
+    rows: &[Vec<Cell>],
+) -> io::Result<()> {
+    let mut zip = ZipWriter::new(output);
+    zip.add("[Content_Types].xml", CONTENT_TYPES.as_bytes())?;
+    zip.add("_rels/.rels", ROOT_RELS.as_bytes())?;
+    zip.add("xl/workbook.xml", WORKBOOK.as_bytes())?;
+    zip.add("xl/_rels/workbook.xml.rels", WORKBOOK_RELS.as_bytes())?;
+    zip.add("xl/styles.xml", STYLES.as_bytes())?;
+    zip.add(
+        "xl/worksheets/sheet1.xml",
+        worksheet(header, rows).as_bytes(),
+    )?;
+    zip.finish()
+}
+
+fn worksheet(header: &[String], rows: &[Vec<Cell>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            let len = match cell {
+                Cell::Str(s) => s.chars().count(),
+                Cell::Number(n) => n.to_string().len(),
+            };
+            *width = (*width).max(len);
+        }
+    }
+
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews><cols>"#,
+    );
+    for (i, width) in widths.iter().enumerate() {
+        let width = width.min(&MAX_COLUMN_WIDTH) + 2;
+        xml.push_str(&format!(
+            r#"<col min="{0}" max="{0}" width="{width}" customWidth="1"/>"#,
+            i + 1
+        ));
+    }
+    xml.push_str("</cols><sheetData>");
+    let header = header.iter().map(|h| Cell::Str(h));
+    write_row(&mut xml, 1, header);
+    for (i, row) in rows.iter().enumerate() {
+        write_row(&mut xml, i + 2, row.iter().copied());
+    }
+    xml.push_str("</sheetData></worksheet>");
+    xml
+}
+
+fn write_row<'a>(xml: &mut String, r: usize, cells: impl Iterator<Item = Cell<'a>>) {
+    xml.push_str(&format!(r#"<row r="{r}">"#));
+    for (i, cell) in cells.enumerate() {
+        let reference = format!("{}{r}", column_name(i));
+        match cell {
+            Cell::Str("") => {}
+            Cell::Str(s) => xml.push_str(&format!(
+                r#"<c r="{reference}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+                escape(s)
+            )),
+            Cell::Number(n) => xml.push_str(&format!(r#"<c r="{reference}"><v>{n}</v></c>"#)),
+        }
+    }
+    xml.push_str("</row>");
+}
+
+/// `s` cut to the maximum number of characters in a cell
+pub(crate) fn truncate_cell(s: &str) -> &str {
+    match s.char_indices().nth(MAX_CELL_LEN) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// Column letters of the zero-based column index (`A`, ..., `Z`, `AA`, ...)
+fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+/// Escape XML special characters and drop characters not allowed in XML
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_name() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(27), "AB");
+        assert_eq!(column_name(701), "ZZ");
+        assert_eq!(column_name(702), "AAA");
+    }
+
+    #[test]
+    fn test_truncate_cell() {
+        assert_eq!(truncate_cell("ID"), "ID");
+        let long = "é".repeat(MAX_CELL_LEN + 1);
+        assert_eq!(truncate_cell(&long).chars().count(), MAX_CELL_LEN);
+        assert_eq!(truncate_cell(&long[2..]), &long[2..]);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a<b & \"c\">"), "a&lt;b &amp; &quot;c&quot;&gt;");
+        assert_eq!(escape("ID\0"), "ID");
+    }
+}
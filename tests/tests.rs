@@ -1,8 +1,49 @@
 use anyhow::Result;
+use dicom_core::value::{DataSetSequence, PixelFragmentSequence};
+use dicom_core::{DataElement, PrimitiveValue, Tag, VR};
+use dicom_dictionary_std::{tags, uids};
+use dicom_object::mem::InMemElement;
+use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use szkdcm::Args;
 
+/// Write a DICOM file with the given elements in addition to the SOP class and instance UIDs
+fn write_dicom(path: &Path, elements: Vec<InMemElement>) -> Result<()> {
+    let mut obj = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+        DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+    ]);
+    for element in elements {
+        obj.put(element);
+    }
+    let file = obj
+        .with_meta(FileMetaTableBuilder::new().transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN))?;
+    file.write_to_file(path)?;
+    Ok(())
+}
+
+/// Write a minimal DICOM file with the given PatientID
+fn write_test_file(path: &Path, patient_id: &str) -> Result<()> {
+    write_dicom(
+        path,
+        vec![
+            DataElement::new(tags::MODALITY, VR::CS, "CT"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, PrimitiveValue::from(patient_id)),
+        ],
+    )
+}
+
+/// Create an empty directory for a test under the cargo tmpdir
+fn test_dir(name: &str) -> Result<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
 #[test]
 fn test_dump() -> Result<()> {
     for name in ["liver", "CT_small"] {
@@ -26,6 +67,7 @@ fn test_dump() -> Result<()> {
             output: Some(output.clone()),
             jobs: None,
             complete: None,
+            ..Default::default()
         };
         let result = szkdcm::main(args);
         result.unwrap();
@@ -62,6 +104,7 @@ fn test_multiple_files() -> Result<()> {
         output: Some(output.clone()),
         jobs: None,
         complete: None,
+        ..Default::default()
     };
 
     szkdcm::main(args)?;
@@ -71,3 +114,2961 @@ fn test_multiple_files() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_recursive_max_depth() -> Result<()> {
+    let dir = test_dir("recursive")?;
+    fs::create_dir_all(dir.join("input/a/b"))?;
+    write_test_file(&dir.join("input/top.dcm"), "TOP")?;
+    write_test_file(&dir.join("input/a/middle.dcm"), "MIDDLE")?;
+    write_test_file(&dir.join("input/a/b/bottom.dcm"), "BOTTOM")?;
+
+    let run = |recursive: bool, max_depth: Option<usize>| -> Result<Vec<String>> {
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![dir.join("input")],
+            tag: vec!["PatientID".to_string()],
+            read_until: "PixelData".to_string(),
+            recursive,
+            max_depth,
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        let content = fs::read_to_string(output)?;
+        let mut ids: Vec<_> = content
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').nth(1).unwrap().to_string())
+            .collect();
+        ids.sort();
+        Ok(ids)
+    };
+
+    assert_eq!(run(false, None)?, ["TOP"]);
+    assert_eq!(run(true, Some(1))?, ["TOP"]);
+    assert_eq!(run(true, Some(2))?, ["MIDDLE", "TOP"]);
+    assert_eq!(run(true, None)?, ["BOTTOM", "MIDDLE", "TOP"]);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_follow_symlinks() -> Result<()> {
+    use std::os::unix::fs::symlink;
+    let dir = test_dir("follow_symlinks")?;
+    fs::create_dir_all(dir.join("input/a"))?;
+    fs::create_dir_all(dir.join("outside"))?;
+    write_test_file(&dir.join("input/top.dcm"), "TOP")?;
+    write_test_file(&dir.join("input/a/middle.dcm"), "MIDDLE")?;
+    write_test_file(&dir.join("outside/linked.dcm"), "LINKED")?;
+    symlink(dir.join("input"), dir.join("input/a/loop"))?;
+    symlink(dir.join("outside"), dir.join("input/outside"))?;
+
+    let run = |follow_symlinks: bool| -> Result<Vec<String>> {
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![dir.join("input")],
+            tag: vec!["PatientID".to_string()],
+            read_until: "PixelData".to_string(),
+            recursive: true,
+            follow_symlinks,
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        let content = fs::read_to_string(output)?;
+        let mut ids: Vec<_> = content
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').nth(1).unwrap().to_string())
+            .collect();
+        ids.sort();
+        Ok(ids)
+    };
+
+    assert_eq!(run(false)?, ["MIDDLE", "TOP"]);
+    assert_eq!(run(true)?, ["LINKED", "MIDDLE", "TOP"]);
+    Ok(())
+}
+
+#[test]
+fn test_num_fragments() -> Result<()> {
+    let dir = test_dir("num_fragments")?;
+    let encapsulated = dir.join("encapsulated.dcm");
+    let obj = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+        DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+        DataElement::new(
+            tags::PIXEL_DATA,
+            VR::OB,
+            PixelFragmentSequence::new(vec![0, 8, 16], vec![vec![0xff; 8]; 3]),
+        ),
+    ]);
+    obj.with_meta(FileMetaTableBuilder::new().transfer_syntax(uids::JPEG_BASELINE8_BIT))?
+        .write_to_file(&encapsulated)?;
+    let native = dir.join("native.dcm");
+    write_test_file(&native, "NATIVE")?;
+
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![encapsulated, native],
+        tag: vec!["@num_fragments".to_string(), "@element_count".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    assert_eq!(
+        content,
+        "FileName,@num_fragments,@element_count\nencapsulated.dcm,3,2\nnative.dcm,,4\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_extract_iter() -> Result<()> {
+    let dir = test_dir("extract_iter")?;
+    let mut paths = Vec::new();
+    for i in 0..5 {
+        let path = dir.join(format!("{i}.dcm"));
+        write_test_file(&path, &format!("ID{i}"))?;
+        paths.push(path);
+    }
+    paths.push(dir.join("missing.dcm"));
+
+    let column: szkdcm::Column = "PatientID".parse()?;
+    let mut results: Vec<_> = szkdcm::extract_iter(paths, tags::PIXEL_DATA, &[column])
+        .map(|(path, row)| (path, row.map(|row| row[&column].clone()).ok()))
+        .collect();
+    results.sort();
+    assert_eq!(results.len(), 6);
+    assert_eq!(results[0], (dir.join("0.dcm"), Some("ID0".to_string())));
+    assert_eq!(results[4], (dir.join("4.dcm"), Some("ID4".to_string())));
+    assert_eq!(results[5], (dir.join("missing.dcm"), None));
+    Ok(())
+}
+
+#[test]
+fn test_extractor() -> Result<()> {
+    let dir = test_dir("extractor")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let extractor = szkdcm::Extractor::from_names("PixelData", &["PatientID", "0008,0060"])?;
+    let row = extractor.extract(&input)?;
+    let columns = extractor.columns();
+    assert_eq!(columns.len(), 2);
+    assert_eq!(row[&columns[0]], "ID");
+    assert_eq!(row[&columns[1]], "CT");
+    assert!(szkdcm::Extractor::from_names("PixelData", &["NoSuchTag"]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_info() -> Result<()> {
+    let dir = test_dir("info")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::PATIENT_ID, VR::LO, "ID"),
+            DataElement::new(tags::PATIENT_SEX, VR::CS, "F"),
+            DataElement::new(tags::STUDY_DATE, VR::DA, "20240301"),
+            DataElement::new(tags::MODALITY, VR::CS, "CT"),
+        ],
+    )?;
+    let patient = szkdcm::patient_info(&input)?;
+    assert_eq!(patient.patient_id, "ID");
+    assert_eq!(patient.patient_sex, "F");
+    assert_eq!(patient.patient_name, "");
+    assert_eq!(szkdcm::study_info(&input)?.study_date, "20240301");
+    assert_eq!(szkdcm::series_info(&input)?.modality, "CT");
+    assert!(szkdcm::patient_info(&dir.join("missing.dcm")).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_csv_comment() -> Result<()> {
+    let dir = test_dir("csv_comment")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        csv_comment: true,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(lines[0], format!("# szkdcm {}", env!("CARGO_PKG_VERSION")));
+    assert!(lines[1].starts_with("# command: "));
+    assert!(lines[2].starts_with("# timestamp: "));
+    assert_eq!(&lines[3..], ["FileName,PatientID", "input.dcm,ID"]);
+    Ok(())
+}
+
+#[test]
+fn test_list_errors_only() -> Result<()> {
+    let dir = test_dir("list_errors_only")?;
+    let valid = dir.join("valid.dcm");
+    write_test_file(&valid, "ID")?;
+    let invalid = dir.join("invalid.dcm");
+    fs::write(&invalid, b"not a dicom file")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![valid, invalid],
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        list_errors_only: true,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "FileName,Error");
+    assert!(lines[1].starts_with("invalid.dcm,"));
+    Ok(())
+}
+
+#[test]
+fn test_no_preamble() -> Result<()> {
+    let dir = test_dir("no_preamble")?;
+    let original = dir.join("original.dcm");
+    write_test_file(&original, "ID")?;
+    let obj = dicom_object::open_file(&original)?;
+    // starts with the DICM magic code
+    let magic_only = dir.join("magic_only.dcm");
+    let mut buf = b"DICM".to_vec();
+    obj.write_meta(&mut buf)?;
+    obj.write_dataset(&mut buf)?;
+    fs::write(&magic_only, &buf)?;
+    // starts directly with the file meta group
+    let meta_only = dir.join("meta_only.dcm");
+    fs::write(&meta_only, &buf[4..])?;
+
+    let run = |input: &Path, no_preamble: bool| -> Result<String> {
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![input.to_path_buf()],
+            tag: vec!["PatientID".to_string()],
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            no_preamble,
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        Ok(fs::read_to_string(output)?)
+    };
+
+    // skipped as a non-DICOM file
+    assert_eq!(run(&meta_only, false)?, "FileName,PatientID\n");
+    for input in [&original, &magic_only, &meta_only] {
+        let name = input.file_name().unwrap().to_str().unwrap();
+        assert_eq!(
+            run(input, true)?,
+            format!("FileName,PatientID\n{name},ID\n")
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_no_preamble_derived() -> Result<()> {
+    let dir = test_dir("no_preamble_derived")?;
+    let original = dir.join("original.dcm");
+    write_dicom(
+        &original,
+        vec![
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2u16)),
+            DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16u16)),
+            DataElement::new(
+                tags::PIXEL_DATA,
+                VR::OW,
+                PrimitiveValue::U16((0..4).collect()),
+            ),
+        ],
+    )?;
+    let obj = dicom_object::open_file(&original)?;
+    let magic_only = dir.join("magic_only.dcm");
+    let mut buf = b"DICM".to_vec();
+    obj.write_meta(&mut buf)?;
+    obj.write_dataset(&mut buf)?;
+    fs::write(&magic_only, &buf)?;
+    let meta_only = dir.join("meta_only.dcm");
+    fs::write(&meta_only, &buf[4..])?;
+
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![original, magic_only, meta_only],
+        tag: vec!["@pixeldata_size_ok".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        no_preamble: true,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,@pixeldata_size_ok\n\
+         original.dcm,true\n\
+         magic_only.dcm,true\n\
+         meta_only.dcm,true\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_code() -> Result<()> {
+    let dir = test_dir("code")?;
+    let input = dir.join("input.dcm");
+    let code = |value: &str, meaning: &str| {
+        InMemDicomObject::from_element_iter([
+            DataElement::new(tags::CODE_VALUE, VR::SH, PrimitiveValue::from(value)),
+            DataElement::new(tags::CODING_SCHEME_DESIGNATOR, VR::SH, "DCM"),
+            DataElement::new(tags::CODE_MEANING, VR::LO, PrimitiveValue::from(meaning)),
+        ])
+    };
+    write_dicom(
+        &input,
+        vec![DataElement::new(
+            tags::PROCEDURE_CODE_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![code("1", "First"), code("2", "Second")]),
+        )],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        code: vec![
+            "ProcedureCodeSequence".to_string(),
+            "AnatomicRegionSequence".to_string(),
+        ],
+        code_value: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "FileName,ProcedureCodeSequence/0/CodeMeaning,ProcedureCodeSequence/0/CodeValue,\
+             AnatomicRegionSequence/0/CodeMeaning,AnatomicRegionSequence/0/CodeValue",
+            "input.dcm,First,1,,",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_utf8_bom() -> Result<()> {
+    let dir = test_dir("utf8_bom")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        utf8_bom: true,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read(output)?;
+    assert_eq!(content, b"\xEF\xBB\xBFFileName,PatientID\ninput.dcm,ID\n");
+    Ok(())
+}
+
+#[test]
+fn test_default() -> Result<()> {
+    let dir = test_dir("default")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec![
+            "PatientID".to_string(),
+            "Modality=MR".to_string(),
+            "BodyPartExamined=NONE".to_string(),
+        ],
+        default: vec![
+            "PatientID=NONE".to_string(),
+            "BodyPartExamined=UNKNOWN".to_string(),
+        ],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    assert_eq!(
+        content,
+        "FileName,PatientID,Modality,BodyPartExamined\ninput.dcm,ID,CT,UNKNOWN\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_ndjson_stream() -> Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let dir = test_dir("ndjson")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let missing = dir.join("missing.dcm");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_szkdcm"))
+        .args(["--ndjson", "-t", "PatientID", "-t", "Modality"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdin_content = format!(
+        "{}\nnot json\n{{\"no_path\": 1}}\n{}\n",
+        serde_json::json!({ "path": input }),
+        serde_json::json!({ "path": missing }),
+    );
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin_content.as_bytes())?;
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+
+    let records: Vec<serde_json::Value> = String::from_utf8(output.stdout)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 2);
+    assert_eq!(
+        records[0],
+        serde_json::json!({ "path": input, "PatientID": "ID", "Modality": "CT" })
+    );
+    assert_eq!(records[1]["path"], serde_json::json!(missing));
+    assert!(records[1]["error"].is_string());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Skipping line 2"));
+    assert!(stderr.contains("Skipping line 3"));
+    Ok(())
+}
+
+#[test]
+fn test_all_tags() -> Result<()> {
+    let dir = test_dir("all_tags")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["PatientID".to_string()],
+        all_tags: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "FileName,PatientID,SOPClassUID,SOPInstanceUID,Modality",
+            &format!("input.dcm,ID,{},1.2.3.4,CT", uids::CT_IMAGE_STORAGE),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_pixel_stats() -> Result<()> {
+    let dir = test_dir("pixel_stats")?;
+    let input = dir.join("input.dcm");
+    let pixels: Vec<u16> = [1i16, 2, 3, 4, -10, 100, 0, 0]
+        .iter()
+        .map(|&v| v as u16)
+        .collect();
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::SAMPLES_PER_PIXEL, VR::US, PrimitiveValue::from(1u16)),
+            DataElement::new(tags::NUMBER_OF_FRAMES, VR::IS, "2"),
+            DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2u16)),
+            DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2u16)),
+            DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16u16)),
+            DataElement::new(tags::BITS_STORED, VR::US, PrimitiveValue::from(16u16)),
+            DataElement::new(tags::HIGH_BIT, VR::US, PrimitiveValue::from(15u16)),
+            DataElement::new(
+                tags::PIXEL_REPRESENTATION,
+                VR::US,
+                PrimitiveValue::from(1u16),
+            ),
+            DataElement::new(tags::PIXEL_DATA, VR::OW, PrimitiveValue::U16(pixels.into())),
+        ],
+    )?;
+
+    let run = |frames: szkdcm::PixelFrames| -> Result<String> {
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![input.clone()],
+            pixel_stats: true,
+            pixel_stats_frames: frames,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        Ok(fs::read_to_string(output)?)
+    };
+    let header = "FileName,@pixel_min,@pixel_max,@pixel_mean";
+    assert_eq!(
+        run(szkdcm::PixelFrames::All)?,
+        format!("{header}\ninput.dcm,-10,100,12.500\n")
+    );
+    assert_eq!(
+        run(szkdcm::PixelFrames::First)?,
+        format!("{header}\ninput.dcm,1,4,2.500\n")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_pixeldata_size_ok() -> Result<()> {
+    let dir = test_dir("pixeldata_size_ok")?;
+    for (name, len) in [("complete.dcm", 8u16), ("truncated.dcm", 6)] {
+        write_dicom(
+            &dir.join(name),
+            vec![
+                DataElement::new(tags::NUMBER_OF_FRAMES, VR::IS, "2"),
+                DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(2u16)),
+                DataElement::new(tags::COLUMNS, VR::US, PrimitiveValue::from(2u16)),
+                DataElement::new(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16u16)),
+                DataElement::new(
+                    tags::PIXEL_DATA,
+                    VR::OW,
+                    PrimitiveValue::U16((0..len).collect()),
+                ),
+            ],
+        )?;
+    }
+    write_dicom(
+        &dir.join("no_pixels.dcm"),
+        vec![DataElement::new(tags::MODALITY, VR::CS, "SR")],
+    )?;
+
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![
+            dir.join("complete.dcm"),
+            dir.join("truncated.dcm"),
+            dir.join("no_pixels.dcm"),
+        ],
+        tag: vec!["@pixeldata_size_ok".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,@pixeldata_size_ok\ncomplete.dcm,true\ntruncated.dcm,false\nno_pixels.dcm,\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_show_color() -> Result<()> {
+    let dir = test_dir("show_color")?;
+    let icc = || {
+        DataElement::new(
+            tags::ICC_PROFILE,
+            VR::OB,
+            PrimitiveValue::from(vec![0u8; 4]),
+        )
+    };
+    write_dicom(
+        &dir.join("rgb.dcm"),
+        vec![
+            DataElement::new(tags::PHOTOMETRIC_INTERPRETATION, VR::CS, "RGB"),
+            icc(),
+        ],
+    )?;
+    write_dicom(
+        &dir.join("wsi.dcm"),
+        vec![
+            DataElement::new(tags::PHOTOMETRIC_INTERPRETATION, VR::CS, "YBR_FULL_422"),
+            DataElement::new(
+                tags::OPTICAL_PATH_SEQUENCE,
+                VR::SQ,
+                DataSetSequence::from(vec![InMemDicomObject::from_element_iter([icc()])]),
+            ),
+        ],
+    )?;
+    write_dicom(
+        &dir.join("gray.dcm"),
+        vec![DataElement::new(
+            tags::PHOTOMETRIC_INTERPRETATION,
+            VR::CS,
+            "MONOCHROME2",
+        )],
+    )?;
+
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: ["rgb.dcm", "wsi.dcm", "gray.dcm"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect(),
+        show_color: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PhotometricInterpretation,@has_icc_profile\n\
+         rgb.dcm,RGB,true\n\
+         wsi.dcm,YBR_FULL_422,true\n\
+         gray.dcm,MONOCHROME2,false\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_json_output() -> Result<()> {
+    let dir = test_dir("json_output")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.csv");
+    let json_output = dir.join("output.json");
+    let ndjson_output = dir.join("output.ndjson");
+    let args = Args {
+        input: vec![input.clone()],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        json_output: Some(json_output.clone()),
+        ndjson_output: Some(ndjson_output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let record = serde_json::json!({ "path": input, "PatientID": "ID", "Modality": "CT" });
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientID,Modality\ninput.dcm,ID,CT\n"
+    );
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(json_output)?)?;
+    assert_eq!(json, serde_json::json!([record]));
+    let ndjson: serde_json::Value = serde_json::from_str(&fs::read_to_string(ndjson_output)?)?;
+    assert_eq!(ndjson, record);
+    Ok(())
+}
+
+#[test]
+fn test_json_output_conflicts() -> Result<()> {
+    let dir = test_dir("json_output_conflicts")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    for mode in [
+        &["--value-counts"][..],
+        &["--distinct-values"],
+        &["--length-stats"],
+        &["--format", "xlsx"],
+        &["--format", "table"],
+        &["--format", "dicom-json"],
+        &["--aggregate-by", "PatientID"],
+        &["--summary"],
+    ] {
+        for json_flag in ["--json-output", "--ndjson-output"] {
+            let output = std::process::Command::new(env!("CARGO_BIN_EXE_szkdcm"))
+                .arg(&input)
+                .args(["-t", "Modality"])
+                .args(mode)
+                .arg(json_flag)
+                .arg(dir.join("output.json"))
+                .output()?;
+            assert!(!output.status.success(), "{mode:?} {json_flag}");
+            assert!(
+                String::from_utf8(output.stderr)?.contains("cannot be used with"),
+                "{mode:?} {json_flag}"
+            );
+        }
+    }
+    assert!(!dir.join("output.json").exists());
+    Ok(())
+}
+
+#[test]
+fn test_embed_provenance() -> Result<()> {
+    let dir = test_dir("embed_provenance")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let json_output = dir.join("output.json");
+    let ndjson_output = dir.join("output.ndjson");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["PatientID".to_string()],
+        embed_provenance: true,
+        read_until: "PixelData".to_string(),
+        output: Some(dir.join("output.csv")),
+        json_output: Some(json_output.clone()),
+        ndjson_output: Some(ndjson_output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(json_output)?)?;
+    let meta = &json[0]["_meta"];
+    assert_eq!(meta["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(meta["tags"], serde_json::json!(["0010,0020"]));
+    assert!(meta["timestamp"].is_string());
+    assert_eq!(json[0]["PatientID"], "ID");
+    let ndjson: serde_json::Value = serde_json::from_str(&fs::read_to_string(ndjson_output)?)?;
+    assert_eq!(&ndjson["_meta"], meta);
+    Ok(())
+}
+
+#[test]
+fn test_require_dicm() -> Result<()> {
+    let dir = test_dir("require_dicm")?;
+    write_test_file(&dir.join("valid.dcm"), "ID")?;
+    fs::write(dir.join("invalid.dcm"), [0u8; 256])?;
+    fs::write(dir.join("short.dcm"), b"DICM")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.clone()],
+        tag: vec!["PatientID".to_string()],
+        require_dicm: true,
+        list_errors_only: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    // skipped files are not errors
+    assert_eq!(fs::read_to_string(output)?, "FileName,Error\n");
+    Ok(())
+}
+
+#[test]
+fn test_skip_non_dicom() -> Result<()> {
+    let dir = test_dir("skip_non_dicom")?;
+    let valid = dir.join("valid.dcm");
+    write_test_file(&valid, "ID")?;
+    let pdf = dir.join("document.pdf");
+    fs::write(&pdf, b"%PDF-1.4")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![valid, pdf],
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientID\nvalid.dcm,ID\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resume_append() -> Result<()> {
+    let dir = test_dir("resume_append")?;
+    let first = dir.join("first.dcm");
+    write_test_file(&first, "ID1")?;
+    let output = dir.join("output.csv");
+    let args = |input: Vec<PathBuf>| Args {
+        input,
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        append: true,
+        resume: Some(output.clone()),
+        ..Default::default()
+    };
+    // start from an output with only the header
+    fs::write(&output, "FileName,PatientID\n")?;
+    szkdcm::main(args(vec![dir.clone()]))?;
+    assert_eq!(
+        fs::read_to_string(&output)?,
+        "FileName,PatientID\nfirst.dcm,ID1\n"
+    );
+
+    let second = dir.join("second.dcm");
+    write_test_file(&second, "ID2")?;
+    szkdcm::main(args(vec![dir.clone()]))?;
+    assert_eq!(
+        fs::read_to_string(&output)?,
+        "FileName,PatientID\nfirst.dcm,ID1\nsecond.dcm,ID2\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resume_ambiguous() -> Result<()> {
+    let dir = test_dir("resume_ambiguous")?;
+    for series in ["series1", "series2"] {
+        fs::create_dir(dir.join(series))?;
+        write_test_file(&dir.join(series).join("IM0001.dcm"), "ID")?;
+    }
+    let output = dir.join("output.csv");
+    fs::write(&output, "FileName,PatientID\nIM0001.dcm,ID\n")?;
+    let args = Args {
+        input: vec![dir.clone()],
+        recursive: true,
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        append: true,
+        resume: Some(output.clone()),
+        ..Default::default()
+    };
+    let e = szkdcm::main(args).unwrap_err();
+    assert!(
+        e.to_string()
+            .contains("are both recorded as \"IM0001.dcm\""),
+        "{e}"
+    );
+    assert_eq!(
+        fs::read_to_string(&output)?,
+        "FileName,PatientID\nIM0001.dcm,ID\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resume_watch() -> Result<()> {
+    let dir = test_dir("resume_watch")?;
+    let input_dir = dir.join("input");
+    fs::create_dir(&input_dir)?;
+    write_test_file(&input_dir.join("first.dcm"), "ID1")?;
+    write_test_file(&input_dir.join("second.dcm"), "ID2")?;
+    let output = dir.join("output.csv");
+    fs::write(&output, "FileName,PatientID\nfirst.dcm,ID1\n")?;
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_szkdcm"))
+        .arg(&input_dir)
+        .args([
+            "-t",
+            "PatientID",
+            "--append",
+            "--watch",
+            "--watch-interval",
+            "0.1",
+        ])
+        .arg("--resume")
+        .arg(&output)
+        .arg("--")
+        .arg(&output)
+        .spawn()?;
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    write_test_file(&input_dir.join("third.dcm"), "ID3")?;
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    child.kill()?;
+    child.wait()?;
+    assert_eq!(
+        fs::read_to_string(&output)?,
+        "FileName,PatientID\nfirst.dcm,ID1\nsecond.dcm,ID2\nthird.dcm,ID3\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_value_counts() -> Result<()> {
+    let dir = test_dir("value_counts")?;
+    for (name, patient_id) in [("a.dcm", "ID1"), ("b.dcm", "ID2"), ("c.dcm", "ID2")] {
+        write_test_file(&dir.join(name), patient_id)?;
+    }
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.clone()],
+        tag: vec!["Modality".to_string(), "PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        value_counts: true,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "Tag,Value,Count\nModality,CT,3\nPatientID,ID2,2\nPatientID,ID1,1\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_item_path() -> Result<()> {
+    let dir = test_dir("item_path")?;
+    let input = dir.join("input.dcm");
+    let reference = |uid: &str| {
+        InMemDicomObject::from_element_iter([DataElement::new(
+            tags::REFERENCED_SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from(uid),
+        )])
+    };
+    write_dicom(
+        &input,
+        vec![DataElement::new(
+            tags::REFERENCED_IMAGE_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![reference("1.2.1"), reference("1.2.2")]),
+        )],
+    )?;
+    let output = dir.join("output.csv");
+    let json_output = dir.join("output.json");
+    let args = Args {
+        input: vec![input.clone()],
+        tag: vec![
+            "ReferencedImageSequence/1/ReferencedSOPInstanceUID".to_string(),
+            "ReferencedImageSequence/*/ReferencedSOPInstanceUID".to_string(),
+        ],
+        read_until: "PixelData".to_string(),
+        value_separator: Some("|".to_string()),
+        output: Some(output.clone()),
+        json_output: Some(json_output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,ReferencedImageSequence/1/ReferencedSOPInstanceUID,\
+         ReferencedImageSequence/*/ReferencedSOPInstanceUID\n\
+         input.dcm,1.2.2,1.2.1|1.2.2\n"
+    );
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(json_output)?)?;
+    assert_eq!(
+        json[0]["ReferencedImageSequence/*/ReferencedSOPInstanceUID"],
+        serde_json::json!(["1.2.1", "1.2.2"])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_json_item_values() -> Result<()> {
+    let dir = test_dir("json_item_values")?;
+    let input = dir.join("input.dcm");
+    let item =
+        |image_type: Option<&str>| {
+            InMemDicomObject::from_element_iter(image_type.map(|value| {
+                DataElement::new(tags::IMAGE_TYPE, VR::CS, PrimitiveValue::from(value))
+            }))
+        };
+    write_dicom(
+        &input,
+        vec![DataElement::new(
+            tags::REFERENCED_IMAGE_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![item(Some("A\\B")), item(Some("C|D")), item(None)]),
+        )],
+    )?;
+    let output = dir.join("output.csv");
+    let json_output = dir.join("output.json");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["ReferencedImageSequence/*/ImageType".to_string()],
+        read_until: "PixelData".to_string(),
+        value_separator: Some("|".to_string()),
+        output: Some(output.clone()),
+        json_output: Some(json_output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,ReferencedImageSequence/*/ImageType\ninput.dcm,A|B|C|D\n"
+    );
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(json_output)?)?;
+    assert_eq!(
+        json[0]["ReferencedImageSequence/*/ImageType"],
+        serde_json::json!(["A|B", "C|D", ""])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_json_numeric_items() -> Result<()> {
+    let dir = test_dir("json_numeric_items")?;
+    let input = dir.join("input.dcm");
+    let reference = |frame: &str| {
+        InMemDicomObject::from_element_iter([DataElement::new(
+            tags::REFERENCED_FRAME_NUMBER,
+            VR::IS,
+            PrimitiveValue::from(frame),
+        )])
+    };
+    write_dicom(
+        &input,
+        vec![DataElement::new(
+            tags::REFERENCED_IMAGE_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![reference("1\\3"), reference(""), reference("5")]),
+        )],
+    )?;
+    let json_output = dir.join("output.json");
+    let args = Args {
+        input: vec![input],
+        tag: vec![
+            "ReferencedImageSequence/*/ReferencedFrameNumber".to_string(),
+            "SliceThickness".to_string(),
+        ],
+        read_until: "PixelData".to_string(),
+        output: Some(dir.join("output.csv")),
+        json_output: Some(json_output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(json_output)?)?;
+    assert_eq!(
+        json[0]["ReferencedImageSequence/*/ReferencedFrameNumber"],
+        serde_json::json!([[1, 3], null, 5])
+    );
+    assert_eq!(json[0]["SliceThickness"], serde_json::Value::Null);
+    Ok(())
+}
+
+#[test]
+fn test_inspect() -> Result<()> {
+    let dir = test_dir("inspect")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_szkdcm"))
+        .args(["inspect", "--max-value-len", "4"])
+        .arg(&input)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<_> = stdout.lines().collect();
+    assert!(lines[0].starts_with("Tag "));
+    assert!(
+        lines
+            .iter()
+            .any(|l| l.starts_with("(0002,0010) TransferSyntaxUID "))
+    );
+    assert!(
+        lines
+            .iter()
+            .any(|l| l.starts_with("(0008,0016) SOPClassUID ") && l.ends_with(" UI   1 1.2…"))
+    );
+    assert!(
+        lines
+            .iter()
+            .any(|l| l.starts_with("(0010,0020) PatientID ") && l.ends_with(" LO   1 ID"))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_date_filter() -> Result<()> {
+    let dir = test_dir("date_filter")?;
+    for (name, date) in [("a.dcm", "20221231"), ("b.dcm", "20230615"), ("c.dcm", "")] {
+        write_dicom(
+            &dir.join(name),
+            vec![DataElement::new(
+                tags::STUDY_DATE,
+                VR::DA,
+                PrimitiveValue::from(date),
+            )],
+        )?;
+    }
+    let run = |include_undated: bool| -> Result<String> {
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![dir.clone()],
+            tag: vec!["SOPInstanceUID".to_string()],
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            date_tag: Some("StudyDate".to_string()),
+            date_from: chrono::NaiveDate::from_ymd_opt(2023, 1, 1),
+            include_undated,
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        let mut lines: Vec<_> = fs::read_to_string(output)?
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        Ok(lines.join("\n"))
+    };
+    assert_eq!(run(false)?, "FileName,SOPInstanceUID\nb.dcm,1.2.3.4");
+    assert_eq!(
+        run(true)?,
+        "FileName,SOPInstanceUID\nb.dcm,1.2.3.4\nc.dcm,1.2.3.4"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dicom_json() -> Result<()> {
+    let dir = test_dir("dicom_json")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.json");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["PatientID".to_string(), "PatientName".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        format: szkdcm::OutputFormat::DicomJson,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(output)?)?;
+    assert_eq!(
+        json,
+        serde_json::json!([{ "00100020": { "vr": "LO", "Value": ["ID"] } }])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_file_size() -> Result<()> {
+    let dir = test_dir("file_size")?;
+    let small = dir.join("small.dcm");
+    write_test_file(&small, "ID")?;
+    let large = dir.join("large.dcm");
+    write_dicom(
+        &large,
+        vec![DataElement::new(
+            tags::PATIENT_COMMENTS,
+            VR::LT,
+            PrimitiveValue::from("x".repeat(4096)),
+        )],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![small, large],
+        tag: vec!["SOPInstanceUID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        max_file_size: Some(4096),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,SOPInstanceUID\nsmall.dcm,1.2.3.4\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_write_tag_file() -> Result<()> {
+    let dir = test_dir("write_tag_file")?;
+    let tag_file = dir.join("tags.txt");
+    let args = Args {
+        tag: vec!["PatientID".to_string()],
+        preset: vec![szkdcm::Preset::Study],
+        read_until: "PixelData".to_string(),
+        write_tag_file: Some(tag_file.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(&tag_file)?;
+    assert!(content.starts_with("0010,0020 # PatientID\n"));
+
+    // the written file can be read back
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag_file: vec![tag_file],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let header = fs::read_to_string(output)?
+        .lines()
+        .next()
+        .unwrap()
+        .to_string();
+    let names: Vec<_> = content
+        .lines()
+        .map(|line| line.split(" # ").nth(1).unwrap())
+        .collect();
+    assert_eq!(header, format!("FileName,{}", names.join(",")));
+    Ok(())
+}
+
+#[test]
+fn test_tag_pattern() -> Result<()> {
+    let dir = test_dir("tag_pattern")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(Tag(0x6000, 0x0022), VR::LO, "First"),
+            DataElement::new(Tag(0x6002, 0x0022), VR::LO, "Second"),
+        ],
+    )?;
+    let run = |tag: &str| -> Result<String> {
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![input.clone()],
+            tag: vec![tag.to_string()],
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        Ok(fs::read_to_string(output)?)
+    };
+    assert_eq!(
+        run("60xx,0022")?,
+        "FileName,OverlayDescription,\"OverlayDescription(6002,0022)\"\n\
+         input.dcm,First,Second\n"
+    );
+    assert_eq!(
+        run("60xx,0022!")?,
+        "FileName,\"60xx,0022!\"\ninput.dcm,First\\Second\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_json_tag_file() -> Result<()> {
+    let dir = test_dir("json_tag_file")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "id")?;
+    let tag_file = dir.join("tags.json");
+    fs::write(
+        &tag_file,
+        r#"[{"tag": "PatientID", "name": "ID", "transform": "upper"}, "Modality"]"#,
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag_file: vec![tag_file],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,ID,Modality\ninput.dcm,ID,CT\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_columns() -> Result<()> {
+    let dir = test_dir("duplicate_columns")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "PID")?;
+    let tag_file = dir.join("tags.json");
+    fs::write(
+        &tag_file,
+        r#"[{"tag": "PatientID", "name": "ID"}, {"tag": "SOPInstanceUID", "name": "ID"}]"#,
+    )?;
+    let output = dir.join("output.csv");
+    let json_output = dir.join("output.json");
+    let args = Args {
+        input: vec![input.clone()],
+        tag: vec!["Modality".to_string(), "00080060".to_string()],
+        tag_file: vec![tag_file],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        json_output: Some(json_output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,Modality,ID,ID_2\ninput.dcm,CT,PID,1.2.3.4\n"
+    );
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(json_output)?)?;
+    assert_eq!(
+        json,
+        serde_json::json!([{ "path": input, "Modality": "CT", "ID": "PID", "ID_2": "1.2.3.4" }])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_summary() -> Result<()> {
+    let dir = test_dir("summary")?;
+    let file = |modality: &str, date: &str| {
+        vec![
+            DataElement::new(tags::MODALITY, VR::CS, PrimitiveValue::from(modality)),
+            DataElement::new(tags::STUDY_DATE, VR::DA, PrimitiveValue::from(date)),
+        ]
+    };
+    write_dicom(&dir.join("1.dcm"), file("CT", "20240301"))?;
+    write_dicom(&dir.join("2.dcm"), file("MR", "20240315"))?;
+    write_dicom(&dir.join("3.dcm"), file("CT", "20240402"))?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.clone()],
+        summary: Some("Modality".to_string()),
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(fs::read_to_string(&output)?, "Modality,Count\nCT,2\nMR,1\n");
+    let args = Args {
+        input: vec![dir],
+        summary: Some("Modality".to_string()),
+        summary_by_month: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "Modality,2024-03,2024-04,Total\nCT,1,1,2\nMR,1,0,1\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dicomdir() -> Result<()> {
+    let dir = test_dir("dicomdir")?;
+    // the names on disk differ in case from the Referenced File IDs
+    fs::create_dir_all(dir.join("dicom").join("st000"))?;
+    write_test_file(&dir.join("dicom").join("st000").join("img1"), "ID1")?;
+    write_test_file(&dir.join("dicom").join("st000").join("IMG2"), "ID2")?;
+    let record = |record_type: &str, file_id: Option<&str>| {
+        let mut record = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::DIRECTORY_RECORD_TYPE,
+            VR::CS,
+            PrimitiveValue::from(record_type),
+        )]);
+        if let Some(file_id) = file_id {
+            record.put(DataElement::new(
+                tags::REFERENCED_FILE_ID,
+                VR::CS,
+                PrimitiveValue::from(file_id),
+            ));
+        }
+        record
+    };
+    let obj = InMemDicomObject::from_element_iter([DataElement::new(
+        tags::DIRECTORY_RECORD_SEQUENCE,
+        VR::SQ,
+        DataSetSequence::from(vec![
+            record("PATIENT", None),
+            record("IMAGE", Some("DICOM\\ST000\\IMG1")),
+            record("IMAGE", Some("DICOM\\ST000\\IMG2")),
+        ]),
+    )]);
+    let file = obj.with_meta(
+        FileMetaTableBuilder::new()
+            .transfer_syntax(uids::EXPLICIT_VR_LITTLE_ENDIAN)
+            .media_storage_sop_class_uid(uids::MEDIA_STORAGE_DIRECTORY_STORAGE)
+            .media_storage_sop_instance_uid("1.2.3"),
+    )?;
+    file.write_to_file(dir.join("DICOMDIR"))?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.join("DICOMDIR")],
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientID\nimg1,ID1\nIMG2,ID2\n"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_value_filter() -> Result<()> {
+    let dir = test_dir("value_filter")?;
+    write_test_file(&dir.join("1.dcm"), "abc")?;
+    write_test_file(&dir.join("2.dcm"), "xyz")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.join("1.dcm"), dir.join("2.dcm")],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        value_filter: vec!["PatientID:tr a-z A-Z | rev".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientID,Modality\n1.dcm,CBA,CT\n2.dcm,ZYX,CT\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_no_trim() -> Result<()> {
+    let dir = test_dir("no_trim")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, " abc")?;
+    let output = dir.join("output.csv");
+    let args = |no_trim| Args {
+        input: vec![input.clone()],
+        tag: vec!["PatientID".to_string()],
+        no_trim,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args(false))?;
+    assert_eq!(
+        fs::read_to_string(&output)?,
+        "FileName,PatientID\ninput.dcm,abc\n"
+    );
+    szkdcm::main(args(true))?;
+    assert_eq!(
+        fs::read_to_string(&output)?,
+        "FileName,PatientID\ninput.dcm, abc\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_no_header() -> Result<()> {
+    let dir = test_dir("no_header")?;
+    write_test_file(&dir.join("1.dcm"), "ID1")?;
+    write_test_file(&dir.join("2.dcm"), "ID2")?;
+    let output = dir.join("output.csv");
+    for input in ["1.dcm", "2.dcm"] {
+        let args = Args {
+            input: vec![dir.join(input)],
+            tag: vec!["PatientID".to_string()],
+            no_header: true,
+            append: true,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+    }
+    assert_eq!(fs::read_to_string(output)?, "1.dcm,ID1\n2.dcm,ID2\n");
+    Ok(())
+}
+
+#[test]
+fn test_show_bytes() -> Result<()> {
+    let dir = test_dir("show_bytes")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "abc")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input.clone()],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        show_bytes: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    // padded to an even length
+    assert_eq!(
+        fs::read_to_string(&output)?,
+        "FileName,PatientID,PatientID_bytes,Modality,Modality_bytes\ninput.dcm,abc,4,CT,2\n"
+    );
+    let args = Args {
+        input: vec![input],
+        all_tags: true,
+        tag_vr: vec![VR::CS, VR::LO],
+        show_bytes: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(&output)?,
+        "FileName,Modality,Modality_bytes,PatientID,PatientID_bytes\ninput.dcm,CT,2,abc,4\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_big_endian() -> Result<()> {
+    let dir = test_dir("big_endian")?;
+    let input = dir.join("input.dcm");
+    let obj = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+        DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+        DataElement::new(tags::PATIENT_ID, VR::LO, "ID"),
+        DataElement::new(tags::ROWS, VR::US, PrimitiveValue::from(512_u16)),
+        DataElement::new(
+            tags::SMALLEST_IMAGE_PIXEL_VALUE,
+            VR::SS,
+            PrimitiveValue::from(-1024_i16),
+        ),
+        DataElement::new(
+            tags::FRAME_INCREMENT_POINTER,
+            VR::AT,
+            PrimitiveValue::from(tags::FRAME_TIME),
+        ),
+        DataElement::new(
+            tags::RECOMMENDED_DISPLAY_FRAME_RATE_IN_FLOAT,
+            VR::FL,
+            PrimitiveValue::from(29.5_f32),
+        ),
+        DataElement::new(
+            tags::REAL_WORLD_VALUE_SLOPE,
+            VR::FD,
+            PrimitiveValue::F64([0.5, 1.25].into()),
+        ),
+        DataElement::new(
+            tags::SIMPLE_FRAME_LIST,
+            VR::UL,
+            PrimitiveValue::U32([1, 65536].into()),
+        ),
+    ]);
+    // Explicit VR Big Endian (retired)
+    let file = obj.with_meta(FileMetaTableBuilder::new().transfer_syntax("1.2.840.10008.1.2.2"))?;
+    file.write_to_file(&input)?;
+    // Rows is encoded in big endian
+    let bytes = fs::read(&input)?;
+    assert!(
+        bytes
+            .windows(10)
+            .any(|w| w == [0x00, 0x28, 0x00, 0x10, b'U', b'S', 0x00, 0x02, 0x02, 0x00])
+    );
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec![
+            "PatientID".to_string(),
+            "Rows".to_string(),
+            "SmallestImagePixelValue".to_string(),
+            "FrameIncrementPointer".to_string(),
+            "RecommendedDisplayFrameRateInFloat".to_string(),
+            "RealWorldValueSlope".to_string(),
+            "SimpleFrameList".to_string(),
+            "@encoding".to_string(),
+        ],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?.lines().nth(1).unwrap(),
+        "input.dcm,ID,512,-1024,\"(0018,1063)\",29.5,0.5\\1.25,1\\65536,ExplicitVRBigEndian"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_output_encoding() -> Result<()> {
+    let dir = test_dir("output_encoding")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::SPECIFIC_CHARACTER_SET, VR::CS, "ISO_IR 192"),
+            DataElement::new(tags::PATIENT_NAME, VR::PN, "Müller^Zoë=山田"),
+        ],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["PatientName".to_string()],
+        output_encoding: szkdcm::OutputEncoding::Latin1,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read(output)?,
+        b"FileName,PatientName\ninput.dcm,M\xFCller^Zo\xEB=??\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_max_buffered() -> Result<()> {
+    let dir = test_dir("max_buffered")?;
+    for i in 0..5 {
+        write_test_file(&dir.join(format!("{i}.dcm")), &format!("ID{i}"))?;
+    }
+    fs::write(dir.join("invalid.dcm"), b"not a dicom file")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.clone()],
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        max_buffered: Some(1),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    let mut lines: Vec<_> = content.lines().collect();
+    assert_eq!(lines[0], "FileName,PatientID");
+    lines.sort();
+    assert_eq!(
+        lines,
+        [
+            "0.dcm,ID0",
+            "1.dcm,ID1",
+            "2.dcm,ID2",
+            "3.dcm,ID3",
+            "4.dcm,ID4",
+            "FileName,PatientID"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_window() -> Result<()> {
+    let dir = test_dir("window")?;
+    let ds = |tag, value: &str| DataElement::new(tag, VR::DS, PrimitiveValue::from(value));
+    write_dicom(
+        &dir.join("paired.dcm"),
+        vec![
+            ds(tags::WINDOW_CENTER, "40\\300"),
+            ds(tags::WINDOW_WIDTH, "400\\1500"),
+        ],
+    )?;
+    write_dicom(
+        &dir.join("mismatched.dcm"),
+        vec![
+            ds(tags::WINDOW_CENTER, "40\\300"),
+            ds(tags::WINDOW_WIDTH, "400"),
+        ],
+    )?;
+    write_dicom(&dir.join("none.dcm"), vec![])?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: ["paired.dcm", "mismatched.dcm", "none.dcm"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect(),
+        tag: vec!["@window".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,@window\n\
+         paired.dcm,40/400\\300/1500\n\
+         mismatched.dcm,40/400\\300/\n\
+         none.dcm,\n"
+    );
+    let output = dir.join("output_separator.csv");
+    let args = Args {
+        input: vec![dir.join("mismatched.dcm")],
+        tag: vec!["@window".to_string()],
+        read_until: "PixelData".to_string(),
+        value_separator: Some("|".to_string()),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,@window\nmismatched.dcm,40/400|300/\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_pixel_spacing_mm() -> Result<()> {
+    let dir = test_dir("pixel_spacing_mm")?;
+    let spacing = |tag, value: &str| DataElement::new(tag, VR::DS, PrimitiveValue::from(value));
+    write_dicom(
+        &dir.join("both.dcm"),
+        vec![
+            spacing(tags::PIXEL_SPACING, "0.5\\0.50"),
+            spacing(tags::IMAGER_PIXEL_SPACING, "0.1\\0.1"),
+        ],
+    )?;
+    write_dicom(
+        &dir.join("imager.dcm"),
+        vec![spacing(tags::IMAGER_PIXEL_SPACING, "0.143\\.143")],
+    )?;
+    write_dicom(&dir.join("none.dcm"), vec![])?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![
+            dir.join("both.dcm"),
+            dir.join("imager.dcm"),
+            dir.join("none.dcm"),
+        ],
+        tag: vec!["@pixel_spacing_mm".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,@pixel_spacing_mm\n\
+         both.dcm,0.5000\\0.5000\n\
+         imager.dcm,0.1430\\0.1430\n\
+         none.dcm,\n"
+    );
+    let output = dir.join("output_separator.csv");
+    let args = Args {
+        input: vec![dir.join("imager.dcm")],
+        tag: vec!["@pixel_spacing_mm".to_string()],
+        read_until: "PixelData".to_string(),
+        value_separator: Some("|".to_string()),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,@pixel_spacing_mm\nimager.dcm,0.1430|0.1430\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dedupe_inputs() -> Result<()> {
+    let dir = test_dir("dedupe_inputs")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input.clone(), dir.clone(), dir.join(".").join("input.dcm")],
+        tag: vec!["PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        dedupe_inputs: true,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientID\ninput.dcm,ID\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_xlsx() -> Result<()> {
+    let dir = test_dir("xlsx")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::PATIENT_ID, VR::LO, "007"),
+            DataElement::new(tags::SLICE_THICKNESS, VR::DS, "0.5"),
+            DataElement::new(tags::SLICE_LOCATION, VR::DS, "1e999"),
+            DataElement::new(
+                tags::STUDY_DESCRIPTION,
+                VR::LO,
+                PrimitiveValue::from("x".repeat(40000)),
+            ),
+        ],
+    )?;
+    let output = dir.join("output.xlsx");
+    let args = Args {
+        input: vec![input],
+        tag: vec![
+            "PatientID".to_string(),
+            "SliceThickness".to_string(),
+            "SliceLocation".to_string(),
+            "StudyDescription".to_string(),
+        ],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        format: szkdcm::OutputFormat::Xlsx,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read(output)?;
+    assert!(content.starts_with(b"PK\x03\x04"));
+    // the entries are stored uncompressed
+    let content = String::from_utf8_lossy(&content);
+    assert!(
+        content.contains(r#"<c r="B2" t="inlineStr"><is><t xml:space="preserve">007</t></is></c>"#)
+    );
+    assert!(content.contains(r#"<c r="C2"><v>0.5</v></c>"#));
+    assert!(
+        content
+            .contains(r#"<c r="D2" t="inlineStr"><is><t xml:space="preserve">1e999</t></is></c>"#)
+    );
+    let description = format!(r#"<t xml:space="preserve">{}</t>"#, "x".repeat(32767));
+    assert!(content.contains(&description));
+    Ok(())
+}
+
+#[test]
+fn test_tag_vr() -> Result<()> {
+    let dir = test_dir("tag_vr")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::MODALITY, VR::CS, "CT"),
+            DataElement::new(tags::PATIENT_NAME, VR::PN, "Doe^John"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "ID"),
+        ],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        all_tags: true,
+        tag_vr: vec![VR::PN, VR::UI],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        format!(
+            "FileName,SOPClassUID,SOPInstanceUID,PatientName\ninput.dcm,{},1.2.3.4,Doe^John\n",
+            uids::CT_IMAGE_STORAGE
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn test_flatten_sequences() -> Result<()> {
+    let dir = test_dir("flatten_sequences")?;
+    let input = dir.join("input.dcm");
+    let instance = |uid: &str| {
+        InMemDicomObject::from_element_iter([DataElement::new(
+            tags::REFERENCED_SOP_INSTANCE_UID,
+            VR::UI,
+            PrimitiveValue::from(uid),
+        )])
+    };
+    let series = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, "1.2"),
+        DataElement::new(
+            tags::REFERENCED_INSTANCE_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![instance("1.2.1"), instance("1.2.2")]),
+        ),
+    ]);
+    write_dicom(
+        &input,
+        vec![DataElement::new(
+            tags::REFERENCED_SERIES_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![series]),
+        )],
+    )?;
+    let run = |max_sequence_depth| -> Result<Vec<String>> {
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![input.clone()],
+            tag: vec!["SOPInstanceUID".to_string()],
+            all_tags: true,
+            flatten_sequences: true,
+            max_sequence_depth,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        let content = fs::read_to_string(output)?;
+        Ok(content.lines().map(String::from).collect())
+    };
+    let lines = run(None)?;
+    let header: Vec<_> = lines[0].split(',').collect();
+    assert_eq!(
+        header[3..],
+        [
+            "ReferencedSeriesSequence",
+            "ReferencedSeriesSequence/0/SeriesInstanceUID",
+            "ReferencedSeriesSequence/0/ReferencedInstanceSequence/0/ReferencedSOPInstanceUID",
+            "ReferencedSeriesSequence/0/ReferencedInstanceSequence/1/ReferencedSOPInstanceUID",
+        ]
+    );
+    assert!(lines[1].ends_with(",1.2,1.2.1,1.2.2"));
+    let lines = run(Some(1))?;
+    assert!(lines[0].ends_with(",ReferencedSeriesSequence/0/SeriesInstanceUID"));
+    Ok(())
+}
+
+#[test]
+fn test_normalize_person_names() -> Result<()> {
+    let dir = test_dir("normalize_person_names")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::PATIENT_NAME, VR::PN, "Doe^John^^Dr"),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "Doe^John"),
+        ],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["PatientName".to_string(), "PatientID".to_string()],
+        read_until: "PixelData".to_string(),
+        normalize_person_names: true,
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    // only PN values are reformatted
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientName,PatientID\ninput.dcm,John Doe,Doe^John\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_split_multivalue() -> Result<()> {
+    let dir = test_dir("split_multivalue")?;
+    let input1 = dir.join("input1.dcm");
+    let input2 = dir.join("input2.dcm");
+    write_dicom(
+        &input1,
+        vec![DataElement::new(
+            tags::IMAGE_TYPE,
+            VR::CS,
+            "ORIGINAL\\PRIMARY\\AXIAL",
+        )],
+    )?;
+    write_dicom(
+        &input2,
+        vec![DataElement::new(
+            tags::IMAGE_TYPE,
+            VR::CS,
+            "DERIVED\\SECONDARY",
+        )],
+    )?;
+    let run = |max_split_values| -> Result<String> {
+        let output = dir.join("output.csv");
+        let args = Args {
+            input: vec![input1.clone(), input2.clone()],
+            tag: vec!["SOPInstanceUID".to_string()],
+            split_multivalue: vec!["ImageType".to_string()],
+            max_split_values,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        Ok(fs::read_to_string(output)?)
+    };
+    let content = run(None)?;
+    let mut lines: Vec<_> = content.lines().collect();
+    lines[1..].sort();
+    assert_eq!(
+        lines,
+        [
+            "FileName,SOPInstanceUID,ImageType_1,ImageType_2,ImageType_3",
+            "input1.dcm,1.2.3.4,ORIGINAL,PRIMARY,AXIAL",
+            "input2.dcm,1.2.3.4,DERIVED,SECONDARY,",
+        ]
+    );
+    let content = run(Some(1))?;
+    assert!(content.starts_with("FileName,SOPInstanceUID,ImageType_1\n"));
+    Ok(())
+}
+
+#[test]
+fn test_split_multivalue_separator() -> Result<()> {
+    let dir = test_dir("split_multivalue_separator")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![DataElement::new(
+            tags::IMAGE_TYPE,
+            VR::CS,
+            "ORIGINAL\\PRIMARY\\A|B",
+        )],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec![],
+        split_multivalue: vec!["ImageType".to_string()],
+        value_separator: Some("|".to_string()),
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,ImageType_1,ImageType_2,ImageType_3\ninput.dcm,ORIGINAL,PRIMARY,A|B\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_value_component_formatting() -> Result<()> {
+    let dir = test_dir("value_component_formatting")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![DataElement::new(
+            tags::IMAGE_TYPE,
+            VR::CS,
+            "ORIGINAL \\ PRIMARY\0\\AX\0IAL",
+        )],
+    )?;
+    let output = dir.join("output.csv");
+    let run = |max_value_len| -> Result<String> {
+        let args = Args {
+            input: vec![input.clone()],
+            tag: vec!["ImageType_1,ImageType_2,ImageType_3".to_string()],
+            max_value_len,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        Ok(fs::read_to_string(&output)?)
+    };
+    assert_eq!(
+        run(None)?,
+        "FileName,ImageType_1,ImageType_2,ImageType_3\ninput.dcm,ORIGINAL,PRIMARY,AXIAL\n"
+    );
+    assert_eq!(
+        run(Some(4))?,
+        "FileName,ImageType_1,ImageType_2,ImageType_3\ninput.dcm,ORI…,PRI…,AXI…\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_hierarchy() -> Result<()> {
+    let dir = test_dir("hierarchy")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::PATIENT_ID, VR::LO, "ID"),
+            DataElement::new(tags::STUDY_INSTANCE_UID, VR::UI, "1.2"),
+            DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, "1.2.3"),
+            DataElement::new(tags::MODALITY, VR::CS, "CT"),
+        ],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["Modality".to_string(), "PatientID".to_string()],
+        hierarchy: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    // PatientID is kept where it was requested
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,StudyInstanceUID,SeriesInstanceUID,Modality,PatientID\n\
+         input.dcm,1.2,1.2.3,CT,ID\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_conformance() -> Result<()> {
+    let dir = test_dir("conformance")?;
+    write_test_file(&dir.join("valid.dcm"), "ID")?;
+    write_dicom(
+        &dir.join("invalid.dcm"),
+        vec![DataElement::new(tags::PATIENT_ID, VR::LO, "ID")],
+    )?;
+    let profile = dir.join("profile.json");
+    fs::write(
+        &profile,
+        format!(
+            r#"{{"{}": ["PatientID", "Modality"]}}"#,
+            uids::CT_IMAGE_STORAGE
+        ),
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.clone()],
+        conformance: Some(profile),
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        format!(
+            "FileName,SOPClassUID,MissingTag\ninvalid.dcm,{},Modality\n",
+            uids::CT_IMAGE_STORAGE
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn test_base64_binary() -> Result<()> {
+    let dir = test_dir("base64_binary")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(
+                Tag(0x0009, 0x1001),
+                VR::OB,
+                PrimitiveValue::from(vec![0u8, 1]),
+            ),
+            DataElement::new(
+                Tag(0x0009, 0x1002),
+                VR::OB,
+                PrimitiveValue::from(vec![0u8; 8]),
+            ),
+        ],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["00091001".to_string(), "00091002".to_string()],
+        base64_binary: Some(4),
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    assert_eq!(content.lines().nth(1), Some("input.dcm,AAE=,<8 bytes>"));
+    Ok(())
+}
+
+#[test]
+fn test_coalesced_retired() -> Result<()> {
+    let dir = test_dir("coalesced_retired")?;
+    write_dicom(
+        &dir.join("current.dcm"),
+        vec![DataElement::new(tags::SLICE_LOCATION, VR::DS, "1.5")],
+    )?;
+    // Location (0020,0050), retired in favor of SliceLocation
+    write_dicom(
+        &dir.join("retired.dcm"),
+        vec![DataElement::new(Tag(0x0020, 0x0050), VR::DS, "2.5")],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.join("current.dcm"), dir.join("retired.dcm")],
+        tag: vec!["SliceLocation".to_string(), "SliceLocation+".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    let mut lines: Vec<_> = content.lines().collect();
+    lines[1..].sort();
+    assert_eq!(
+        lines,
+        [
+            "FileName,SliceLocation,SliceLocation+",
+            "current.dcm,1.5,1.5",
+            "retired.dcm,,2.5",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_transpose() -> Result<()> {
+    let dir = test_dir("transpose")?;
+    write_test_file(&dir.join("a.dcm"), "A")?;
+    write_test_file(&dir.join("b.dcm"), "B")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.join("a.dcm"), dir.join("b.dcm")],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        transpose: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,a.dcm,b.dcm\nPatientID,A,B\nModality,CT,CT\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_sort_columns() -> Result<()> {
+    let dir = test_dir("sort_columns")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec![
+            "SOPInstanceUID".to_string(),
+            "PatientID".to_string(),
+            "Modality".to_string(),
+        ],
+        sort_columns: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,Modality,PatientID,SOPInstanceUID\ninput.dcm,CT,ID,1.2.3.4\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_exclude_empty_columns() -> Result<()> {
+    let dir = test_dir("exclude_empty_columns")?;
+    write_test_file(&dir.join("1.dcm"), "ID1")?;
+    write_test_file(&dir.join("2.dcm"), "")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.join("1.dcm"), dir.join("2.dcm")],
+        tag: vec![
+            "PatientName".to_string(),
+            "PatientID".to_string(),
+            "StudyDate".to_string(),
+        ],
+        default: vec!["StudyDate=unknown".to_string()],
+        exclude_empty_columns: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientID,StudyDate\n1.dcm,ID1,unknown\n2.dcm,,unknown\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_skip_empty_rows() -> Result<()> {
+    let dir = test_dir("skip_empty_rows")?;
+    write_test_file(&dir.join("1.dcm"), "ID1")?;
+    write_test_file(&dir.join("2.dcm"), "")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.join("1.dcm"), dir.join("2.dcm")],
+        tag: vec!["PatientID".to_string(), "StudyDate".to_string()],
+        skip_empty_rows: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientID,StudyDate\n1.dcm,ID1,\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_aggregate_by() -> Result<()> {
+    let dir = test_dir("aggregate_by")?;
+    let slice = |series: &str, location: &str| {
+        vec![
+            DataElement::new(tags::MODALITY, VR::CS, "CT"),
+            DataElement::new(
+                tags::SERIES_INSTANCE_UID,
+                VR::UI,
+                PrimitiveValue::from(series),
+            ),
+            DataElement::new(tags::SLICE_LOCATION, VR::DS, PrimitiveValue::from(location)),
+        ]
+    };
+    write_dicom(&dir.join("1.dcm"), slice("1.1", "-2.5"))?;
+    write_dicom(&dir.join("2.dcm"), slice("1.1", "10"))?;
+    write_dicom(&dir.join("3.dcm"), slice("1.2", "0"))?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: (1..=3).map(|i| dir.join(format!("{i}.dcm"))).collect(),
+        tag: vec!["Modality".to_string()],
+        aggregate_by: Some("SeriesInstanceUID".to_string()),
+        aggregate: vec![
+            "SliceLocation=min".to_string(),
+            "SliceLocation=max".to_string(),
+        ],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "SeriesInstanceUID,NumFiles,Modality,SliceLocation (min),SliceLocation (max)\n\
+         1.1,2,CT,-2.5,10\n\
+         1.2,1,CT,0,0\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_selftest() -> Result<()> {
+    let args = Args {
+        command: Some(szkdcm::Command::Selftest),
+        ..Default::default()
+    };
+    szkdcm::main(args)
+}
+
+#[test]
+fn test_label() -> Result<()> {
+    let dir = test_dir("label")?;
+    for (name, patient_id) in [("control", "C1"), ("treatment", "T1")] {
+        fs::create_dir(dir.join(name))?;
+        write_test_file(&dir.join(name).join("input.dcm"), patient_id)?;
+    }
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.join("control"), dir.join("treatment")],
+        tag: vec!["PatientID".to_string()],
+        label: vec![format!("{}=placebo", dir.join("control").display())],
+        label_inputs: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,Cohort,PatientID\ninput.dcm,placebo,C1\ninput.dcm,treatment,T1\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_metadata_hash() -> Result<()> {
+    let dir = test_dir("metadata_hash")?;
+    write_test_file(&dir.join("explicit.dcm"), "ID")?;
+    let obj = InMemDicomObject::from_element_iter([
+        DataElement::new(tags::SOP_CLASS_UID, VR::UI, uids::CT_IMAGE_STORAGE),
+        DataElement::new(tags::SOP_INSTANCE_UID, VR::UI, "1.2.3.4"),
+        DataElement::new(tags::MODALITY, VR::CS, "CT"),
+        DataElement::new(tags::PATIENT_ID, VR::LO, "ID"),
+    ]);
+    obj.with_meta(FileMetaTableBuilder::new().transfer_syntax(uids::IMPLICIT_VR_LITTLE_ENDIAN))?
+        .write_to_file(dir.join("implicit.dcm"))?;
+    write_test_file(&dir.join("other.dcm"), "OTHER")?;
+
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![
+            dir.join("explicit.dcm"),
+            dir.join("implicit.dcm"),
+            dir.join("other.dcm"),
+        ],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        metadata_hash: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let content = fs::read_to_string(output)?;
+    let hashes: Vec<_> = content
+        .lines()
+        .skip(1)
+        .map(|line| line.rsplit(',').next().unwrap())
+        .collect();
+    assert_eq!(hashes.len(), 3);
+    assert_eq!(hashes[0], hashes[1]);
+    assert_ne!(hashes[0], hashes[2]);
+    assert_eq!(hashes[0].len(), 32);
+    Ok(())
+}
+
+#[test]
+fn test_max_read_bytes() -> Result<()> {
+    let dir = test_dir("max_read_bytes")?;
+    let input = dir.join("input.dcm");
+    let item = InMemDicomObject::from_element_iter([DataElement::new(
+        tags::REFERENCED_SOP_INSTANCE_UID,
+        VR::UI,
+        "1.2.3.5",
+    )]);
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::STUDY_DESCRIPTION, VR::LO, "Chest"),
+            DataElement::new(
+                tags::REFERENCED_IMAGE_SEQUENCE,
+                VR::SQ,
+                DataSetSequence::from(vec![item]),
+            ),
+            DataElement::new(tags::PATIENT_ID, VR::LO, "ID"),
+            DataElement::new(tags::PATIENT_COMMENTS, VR::LT, "x".repeat(64)),
+        ],
+    )?;
+    let file_len = fs::metadata(&input)?.len();
+    let expected = [
+        "Chest".to_string(),
+        "1.2.3.5".to_string(),
+        "ID".to_string(),
+        "x".repeat(64),
+    ];
+
+    let output = dir.join("output.csv");
+    let mut n_read = Vec::new();
+    // from within the file meta group to the end of the file
+    for max_bytes in (200..file_len).step_by(3).chain([file_len]) {
+        let args = Args {
+            input: vec![input.clone()],
+            tag: vec![
+                "StudyDescription".to_string(),
+                "ReferencedImageSequence/0/ReferencedSOPInstanceUID".to_string(),
+                "PatientID".to_string(),
+                "PatientComments".to_string(),
+            ],
+            max_read_bytes: Some(max_bytes),
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        let content = fs::read_to_string(&output)?;
+        let Some(row) = content.lines().nth(1) else {
+            // the file meta group is cut off
+            continue;
+        };
+        let values: Vec<_> = row.split(',').skip(1).collect();
+        // the values read are a prefix of the elements
+        let n = values.iter().take_while(|v| !v.is_empty()).count();
+        assert!(
+            values[n..].iter().all(|v| v.is_empty()),
+            "{max_bytes}: {row}"
+        );
+        assert_eq!(values[..n], expected[..n], "{max_bytes}");
+        n_read.push(n);
+    }
+    assert_eq!(n_read.first(), Some(&0));
+    assert_eq!(n_read.last(), Some(&4));
+    assert!(n_read.is_sorted());
+    Ok(())
+}
+
+#[test]
+fn test_table_format() -> Result<()> {
+    let dir = test_dir("table_format")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let output = dir.join("output.txt");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        format: szkdcm::OutputFormat::Table,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName  | PatientID | Modality\n\
+         ----------+-----------+---------\n\
+         input.dcm | ID        | CT\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dicom_json_sequence() -> Result<()> {
+    let dir = test_dir("dicom_json_sequence")?;
+    let input = dir.join("input.dcm");
+    let item = InMemDicomObject::from_element_iter([DataElement::new(
+        tags::REFERENCED_SOP_INSTANCE_UID,
+        VR::UI,
+        "1.2.3.5",
+    )]);
+    write_dicom(
+        &input,
+        vec![DataElement::new(
+            tags::REFERENCED_IMAGE_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![item]),
+        )],
+    )?;
+    let run = |max_json_depth| -> Result<serde_json::Value> {
+        let output = dir.join("output.json");
+        let args = Args {
+            input: vec![input.clone()],
+            tag: vec!["ReferencedImageSequence".to_string()],
+            format: szkdcm::OutputFormat::DicomJson,
+            max_json_depth,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        Ok(serde_json::from_str(&fs::read_to_string(output)?)?)
+    };
+    assert_eq!(
+        run(None)?,
+        serde_json::json!([{ "00081140": { "vr": "SQ", "Value": [
+            { "00081155": { "vr": "UI", "Value": ["1.2.3.5"] } }
+        ] } }])
+    );
+    assert_eq!(
+        run(Some(0))?,
+        serde_json::json!([{ "00081140": { "vr": "SQ", "Value": [] } }])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_dictionary() -> Result<()> {
+    let dir = test_dir("dictionary")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(Tag(0x0029, 0x1010), VR::LO, "1"),
+            DataElement::new(Tag(0x0029, 0x1020), VR::LO, "label"),
+        ],
+    )?;
+    // private tags so that the keywords do not affect the other tests
+    let dictionary = dir.join("dictionary.txt");
+    fs::write(
+        &dictionary,
+        "# vendor keywords\n(0029,1010) VendorIndex\n00291020  VendorLabel\n",
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec!["VendorLabel".to_string(), "0029,1010".to_string()],
+        dictionary: Some(dictionary),
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,VendorLabel,VendorIndex\ninput.dcm,label,1\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_show_sequence_count() -> Result<()> {
+    let dir = test_dir("show_sequence_count")?;
+    let input = dir.join("input.dcm");
+    let item = || {
+        InMemDicomObject::from_element_iter([DataElement::new(
+            tags::REFERENCED_SOP_INSTANCE_UID,
+            VR::UI,
+            "1.2.3.5",
+        )])
+    };
+    write_dicom(
+        &input,
+        vec![
+            DataElement::new(tags::PATIENT_ID, VR::LO, "ID"),
+            DataElement::new(
+                tags::REFERENCED_IMAGE_SEQUENCE,
+                VR::SQ,
+                DataSetSequence::from(vec![item(), item(), item()]),
+            ),
+        ],
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag: vec![
+            "ReferencedImageSequence".to_string(),
+            "SourceImageSequence".to_string(),
+            "PatientID".to_string(),
+            "PatientID_items".to_string(),
+        ],
+        show_sequence_count: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,ReferencedImageSequence,ReferencedImageSequence_items,\
+         SourceImageSequence,SourceImageSequence_items,PatientID,PatientID_items\n\
+         input.dcm,,3,,,ID,\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_json_key_style() -> Result<()> {
+    let dir = test_dir("json_key_style")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "ID")?;
+    let ndjson_output = dir.join("output.ndjson");
+    let args = Args {
+        input: vec![input.clone()],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        json_key_style: szkdcm::JsonKeyStyle::Hex,
+        read_until: "PixelData".to_string(),
+        output: Some(dir.join("output.csv")),
+        ndjson_output: Some(ndjson_output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    let ndjson: serde_json::Value = serde_json::from_str(&fs::read_to_string(ndjson_output)?)?;
+    assert_eq!(
+        ndjson,
+        serde_json::json!({ "path": input, "00100020": "ID", "00080060": "CT" })
+    );
+    Ok(())
+}
+
+#[test]
+fn test_max_errors() -> Result<()> {
+    let dir = test_dir("max_errors")?;
+    let mut inputs = vec![dir.join("input.dcm")];
+    write_test_file(&inputs[0], "ID")?;
+    for i in 0..3 {
+        let input = dir.join(format!("broken{i}.dcm"));
+        fs::write(&input, b"not a DICOM file")?;
+        inputs.push(input);
+    }
+    let run = |max_errors| {
+        let args = Args {
+            input: inputs.clone(),
+            tag: vec!["PatientID".to_string()],
+            max_errors: Some(max_errors),
+            read_until: "PixelData".to_string(),
+            output: Some(dir.join("output.csv")),
+            ..Default::default()
+        };
+        szkdcm::main(args)
+    };
+    let e = run(2).unwrap_err();
+    assert!(
+        e.to_string().starts_with("Stopped since more than 2 files"),
+        "{e}"
+    );
+    run(3)?;
+    assert_eq!(
+        fs::read_to_string(dir.join("output.csv"))?,
+        "FileName,PatientID\ninput.dcm,ID\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_split_by() -> Result<()> {
+    let dir = test_dir("split_by")?;
+    let mut inputs = Vec::new();
+    for (i, name) in ["Doe^John", "CT/MR", "Doe^John"].iter().enumerate() {
+        let input = dir.join(format!("{i}.dcm"));
+        write_dicom(
+            &input,
+            vec![DataElement::new(tags::PATIENT_NAME, VR::PN, *name)],
+        )?;
+        inputs.push(input);
+    }
+    let output = dir.join("split");
+    let run = |replace| {
+        let args = Args {
+            input: inputs.clone(),
+            tag: vec!["PatientName".to_string()],
+            split_by: Some("PatientName".to_string()),
+            replace_separators_in_filename: replace,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)
+    };
+    let e = run(false).unwrap_err();
+    assert!(
+        e.to_string().contains("--replace-separators-in-filename"),
+        "{e}"
+    );
+    run(true)?;
+    assert_eq!(
+        fs::read_to_string(output.join("Doe^John.csv"))?,
+        "FileName,PatientName\n0.dcm,Doe^John\n2.dcm,Doe^John\n"
+    );
+    assert_eq!(
+        fs::read_to_string(output.join("CT_MR.csv"))?,
+        "FileName,PatientName\n1.dcm,CT/MR\n"
+    );
+    assert_eq!(
+        fs::read_to_string(output.join("filenames.csv"))?,
+        "PatientName,FileName\nDoe^John,Doe^John.csv\nCT/MR,CT_MR.csv\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_split_by_case_fold() -> Result<()> {
+    let dir = test_dir("split_by_case_fold")?;
+    let mut inputs = Vec::new();
+    for (i, modality) in ["CT", "ct", "MR"].iter().enumerate() {
+        let input = dir.join(format!("{i}.dcm"));
+        write_dicom(
+            &input,
+            vec![DataElement::new(tags::MODALITY, VR::CS, *modality)],
+        )?;
+        inputs.push(input);
+    }
+    let output = dir.join("split");
+    let args = Args {
+        input: inputs,
+        tag: vec!["Modality".to_string()],
+        split_by: Some("Modality".to_string()),
+        case_fold_values: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output.join("CT.csv"))?,
+        "FileName,Modality\n0.dcm,CT\n1.dcm,ct\n"
+    );
+    assert_eq!(
+        fs::read_to_string(output.join("MR.csv"))?,
+        "FileName,Modality\n2.dcm,MR\n"
+    );
+    assert_eq!(fs::read_dir(&output)?.count(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_compute_series_index() -> Result<()> {
+    let dir = test_dir("compute_series_index")?;
+    let mut inputs = Vec::new();
+    for (name, series, number) in [
+        ("a10.dcm", Some("1.2.1"), Some("10")),
+        ("a2.dcm", Some("1.2.1"), Some("2")),
+        ("a_.dcm", Some("1.2.1"), None),
+        ("b1.dcm", Some("1.2.2"), Some("1")),
+        ("none.dcm", None, Some("1")),
+    ] {
+        let mut elements = Vec::new();
+        if let Some(series) = series {
+            elements.push(DataElement::new(tags::SERIES_INSTANCE_UID, VR::UI, series));
+        }
+        if let Some(number) = number {
+            elements.push(DataElement::new(tags::INSTANCE_NUMBER, VR::IS, number));
+        }
+        let input = dir.join(name);
+        write_dicom(&input, elements)?;
+        inputs.push(input);
+    }
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: inputs,
+        compute_series_index: true,
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,InstanceNumber,@series_index\n\
+         a10.dcm,10,1\n\
+         a2.dcm,2,0\n\
+         a_.dcm,,2\n\
+         b1.dcm,1,0\n\
+         none.dcm,1,\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_map() -> Result<()> {
+    let dir = test_dir("map")?;
+    write_test_file(&dir.join("1.dcm"), "A1")?;
+    write_test_file(&dir.join("2.dcm"), "B2")?;
+    let lookup = dir.join("lookup.csv");
+    fs::write(&lookup, "A1,Alice\nCT,Computed Tomography\n")?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.join("1.dcm"), dir.join("2.dcm")],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        map: vec![format!("PatientID:{}", lookup.display())],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,PatientID,Modality\n1.dcm,Alice,CT\n2.dcm,B2,CT\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_length_stats() -> Result<()> {
+    let dir = test_dir("length_stats")?;
+    for (name, patient_id) in [("a.dcm", "ID1"), ("b.dcm", "ID100"), ("c.dcm", "")] {
+        write_test_file(&dir.join(name), patient_id)?;
+    }
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.clone()],
+        tag: vec!["PatientID".to_string(), "StudyDate".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        length_stats: true,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "Tag,MinLen,MaxLen,NonEmptyCount\nPatientID,3,5,2\nStudyDate,,,0\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_tag_file_override() -> Result<()> {
+    let dir = test_dir("tag_file_override")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "id")?;
+    let team = dir.join("team.json");
+    fs::write(
+        &team,
+        r#"[{"tag": "PatientID", "name": "ID", "transform": "upper"}, "Modality"]"#,
+    )?;
+    let project = dir.join("project.json");
+    fs::write(
+        &project,
+        r#"[{"tag": "Modality", "name": "Mod"}, {"tag": "PatientID", "name": "Patient"}]"#,
+    )?;
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![input],
+        tag_file: vec![team, project],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "FileName,Patient,Mod\ninput.dcm,ID,CT\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_output_null_bytes_policy() -> Result<()> {
+    let dir = test_dir("output_null_bytes_policy")?;
+    let input = dir.join("input.dcm");
+    write_dicom(
+        &input,
+        vec![DataElement::new(tags::PATIENT_ID, VR::LO, "AB\0CD")],
+    )?;
+    let output = dir.join("output.csv");
+    let run = |output_null_bytes_policy| -> Result<String> {
+        let args = Args {
+            input: vec![input.clone()],
+            tag: vec!["PatientID".to_string()],
+            output_null_bytes_policy,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        Ok(fs::read_to_string(&output)?)
+    };
+    assert_eq!(
+        run(szkdcm::NullBytesPolicy::Strip)?,
+        "FileName,PatientID\ninput.dcm,ABCD\n"
+    );
+    assert_eq!(
+        run(szkdcm::NullBytesPolicy::Escape)?,
+        "FileName,PatientID\ninput.dcm,AB\\0CD\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_csv_quoting() -> Result<()> {
+    let dir = test_dir("csv_quoting")?;
+    let input = dir.join("input.dcm");
+    write_test_file(&input, "A,B")?;
+    let output = dir.join("output.csv");
+    let run = |csv_quoting, csv_terminator| -> Result<String> {
+        let args = Args {
+            input: vec![input.clone()],
+            tag: vec!["PatientID".to_string(), "Modality".to_string()],
+            csv_quoting,
+            csv_terminator,
+            read_until: "PixelData".to_string(),
+            output: Some(output.clone()),
+            ..Default::default()
+        };
+        szkdcm::main(args)?;
+        Ok(fs::read_to_string(&output)?)
+    };
+    use szkdcm::{CsvQuoting, CsvTerminator};
+    assert_eq!(
+        run(CsvQuoting::Necessary, CsvTerminator::Lf)?,
+        "FileName,PatientID,Modality\ninput.dcm,\"A,B\",CT\n"
+    );
+    assert_eq!(
+        run(CsvQuoting::Always, CsvTerminator::Crlf)?,
+        "\"FileName\",\"PatientID\",\"Modality\"\r\n\"input.dcm\",\"A,B\",\"CT\"\r\n"
+    );
+    assert_eq!(
+        run(CsvQuoting::Never, CsvTerminator::Lf)?,
+        "FileName,PatientID,Modality\ninput.dcm,A,B,CT\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_distinct_values() -> Result<()> {
+    let dir = test_dir("distinct_values")?;
+    for (name, patient_id) in [
+        ("a.dcm", "ID2"),
+        ("b.dcm", "ID1"),
+        ("c.dcm", "ID2"),
+        ("d.dcm", ""),
+    ] {
+        write_test_file(&dir.join(name), patient_id)?;
+    }
+    let output = dir.join("output.csv");
+    let args = Args {
+        input: vec![dir.clone()],
+        tag: vec!["PatientID".to_string(), "Modality".to_string()],
+        read_until: "PixelData".to_string(),
+        output: Some(output.clone()),
+        distinct_values: true,
+        ..Default::default()
+    };
+    szkdcm::main(args)?;
+    assert_eq!(
+        fs::read_to_string(output)?,
+        "PatientID,Modality\nID1\\ID2,CT\n"
+    );
+    Ok(())
+}